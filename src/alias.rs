@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use tokio::fs;
+use crate::misc::{nvm_path, NVM_ALIAS_DIR};
+use crate::nvmrc::{resolve_nvmrc_version, LTS_STR_START, MAX_RECURSIVE_DEREF};
+use crate::version::{NodeVersion, Version, VersionSpec, find_version};
+
+/// An alias made with `nvm alias`, or one of the
+/// `lts/CODENAME` aliases nvm keeps for LTS release lines
+pub struct Alias {
+    /// e.g. `default` or `lts/iron`
+    pub name: String,
+    /// What the alias points to, e.g. `v20.11.0` or `lts/iron`
+    pub target: String,
+}
+
+/// Reads what an alias points to, None if there's no such alias
+pub async fn read_alias(name: &str) -> Option<String> {
+    fs::read_to_string(format!("{}/{}", nvm_path(NVM_ALIAS_DIR), name))
+        .await
+        .ok()
+        .map(|target| target.trim().to_string())
+}
+
+/// Lists the regular aliases sorted by name,
+/// followed by the LTS ones
+pub async fn list_aliases() -> Result<Vec<Alias>> {
+    let alias_dir = nvm_path(NVM_ALIAS_DIR);
+    let mut aliases = list_alias_dir(&alias_dir, "").await?;
+    let lts_dir = format!("{}/{}", alias_dir, LTS_STR_START.trim_end_matches('/'));
+    aliases.append(&mut list_alias_dir(&lts_dir, LTS_STR_START).await?);
+    Ok(aliases)
+}
+
+async fn list_alias_dir(dir: &str, prefix: &str) -> Result<Vec<Alias>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        // nvm only creates the alias dirs when it needs them
+        Err(_) => return Ok(vec![]),
+    };
+
+    let mut aliases = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+
+        if let Ok(target) = fs::read_to_string(entry.path()).await {
+            aliases.push(Alias {
+                name: format!("{}{}", prefix, entry.file_name().to_string_lossy()),
+                target: target.trim().to_string(),
+            });
+        }
+    }
+
+    aliases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(aliases)
+}
+
+/// Follows an alias through any other aliases it points to, e.g.
+/// `["work", "lts/iron", "v20.11.0"]`. Stops at the first target
+/// that isn't an alias or that's already in the chain
+pub async fn alias_chain(name: &str) -> Vec<String> {
+    let mut chain = vec![name.to_string()];
+
+    for _ in 0..=MAX_RECURSIVE_DEREF {
+        let target = match read_alias(chain.last().unwrap()).await {
+            Some(target) => target,
+            None => break,
+        };
+        let is_cycle = chain.contains(&target);
+        chain.push(target);
+
+        if is_cycle {
+            break;
+        }
+    }
+
+    chain
+}
+
+/// Where an alias chain ends up
+pub enum AliasStatus {
+    Installed(Version),
+    /// A valid version that isn't installed, None if it
+    /// needs the mirror to find out which version it is
+    NotInstalled(Option<Version>),
+    System,
+    /// Ends at something that isn't a valid version
+    Dangling,
+    /// Points back to an alias earlier in the chain
+    Cyclic,
+    /// Has more aliases than `MAX_RECURSIVE_DEREF` allows
+    TooDeep,
+}
+
+/// Works out where an alias chain from `alias_chain` ends up. This
+/// never asks the mirror about LTS aliases nvm doesn't have
+pub async fn alias_status(chain: &[String]) -> AliasStatus {
+    let target = chain.last().unwrap();
+    if chain[..chain.len() - 1].contains(target) {
+        return AliasStatus::Cyclic;
+    }
+    if chain.len() > MAX_RECURSIVE_DEREF as usize + 1 {
+        return AliasStatus::TooDeep;
+    }
+    if target.starts_with(LTS_STR_START) {
+        return AliasStatus::NotInstalled(None);
+    }
+
+    match resolve_nvmrc_version(target, 0).await {
+        Ok(NodeVersion::NvmVersion(Some(version))) => match find_version(&version).await {
+            Ok(installed_version) => AliasStatus::Installed(installed_version),
+            Err(_) => AliasStatus::NotInstalled(Some(version)),
+        },
+        Ok(NodeVersion::NvmVersion(None)) => AliasStatus::NotInstalled(None),
+        Ok(NodeVersion::System) => AliasStatus::System,
+        Err(_) => AliasStatus::Dangling,
+    }
+}
+
+/// The installed version at the end of an alias chain, if there is one
+pub async fn resolve_alias_chain(chain: &[String]) -> Option<Version> {
+    match alias_status(chain).await {
+        AliasStatus::Installed(version) => Some(version),
+        _ => None,
+    }
+}
+
+/// Names that mean something to nvm already and can't be aliases
+const RESERVED_NAMES: [&str; 8] = ["node", "stable", "unstable", "iojs", "system", "lts", "rc", "nightly"];
+
+/// Creates or updates an alias like `nvm alias NAME TARGET`, as long
+/// as the target is something an `.nvmrc` could contain
+pub async fn set_alias(name: &str, target: &str) -> Result<()> {
+    let target = target.trim();
+    let is_valid_name = !name.is_empty()
+        && !name.contains(['/', '\\'])
+        && !name.starts_with('.')
+        && !RESERVED_NAMES.contains(&name)
+        && name.parse::<VersionSpec>().is_err();
+    if !is_valid_name {
+        return Err(anyhow!("'{}' can't be used as an alias name", name));
+    }
+
+    // An alias pointing back to itself would never resolve
+    let target_chain = alias_chain(target).await;
+    if target_chain.iter().any(|link| link == name) {
+        return Err(anyhow!("alias cycle {} -> {}", name, target_chain.join(" -> ")));
+    }
+    // Starting one level deep counts the new alias itself
+    resolve_nvmrc_version(target, 1)
+        .await
+        .with_context(|| format!("invalid alias target '{}'", target))?;
+
+    let alias_dir = nvm_path(NVM_ALIAS_DIR);
+    fs::create_dir_all(&alias_dir).await?;
+    fs::write(format!("{}/{}", alias_dir, name), format!("{}\n", target))
+        .await
+        .with_context(|| format!("couldn't write alias {}", name))?;
+    Ok(())
+}
+
+/// Deletes an alias like `nvm unalias NAME`
+pub async fn remove_alias(name: &str) -> Result<()> {
+    // Only top-level aliases, nvm manages the LTS ones itself
+    let is_alias = !name.is_empty()
+        && !name.contains(['/', '\\'])
+        && !name.starts_with('.')
+        && read_alias(name).await.is_some();
+    if !is_alias {
+        return Err(anyhow!("alias {} doesn't exist", name));
+    }
+
+    fs::remove_file(format!("{}/{}", nvm_path(NVM_ALIAS_DIR), name))
+        .await
+        .with_context(|| format!("couldn't remove alias {}", name))
+}
+
+/// Every alias with its whole chain
+pub async fn describe_aliases() -> Result<AliasListing> {
+    let mut aliases = vec![];
+    for alias in list_aliases().await? {
+        aliases.push(AliasInfo::new(&alias.name).await);
+    }
+    Ok(AliasListing { aliases })
+}
+
+/// A single alias with its whole chain
+pub async fn describe_alias(name: &str) -> Result<AliasInfo> {
+    if read_alias(name).await.is_none() {
+        return Err(anyhow!("alias {} doesn't exist", name));
+    }
+    Ok(AliasInfo::new(name).await)
+}
+
+/// Every alias, for `quicknvm alias` without a name
+pub struct AliasListing {
+    pub aliases: Vec<AliasInfo>,
+}
+
+impl AliasListing {
+    pub fn to_text(&self) -> String {
+        self.aliases
+            .iter()
+            .map(AliasInfo::to_text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let aliases = self.aliases
+            .iter()
+            .map(AliasInfo::json_value)
+            .collect::<Vec<_>>();
+        Value::from(aliases).to_string()
+    }
+}
+
+/// An alias along with its whole chain, for `quicknvm alias`
+pub struct AliasInfo {
+    pub name: String,
+    pub chain: Vec<String>,
+    pub status: AliasStatus,
+}
+
+impl AliasInfo {
+    pub async fn new(name: &str) -> Self {
+        let chain = alias_chain(name).await;
+        let status = alias_status(&chain).await;
+        AliasInfo {
+            name: name.to_string(),
+            chain,
+            status,
+        }
+    }
+
+    /// e.g. `default -> lts/hydrogen -> v18.19.0 (installed)`
+    pub fn to_text(&self) -> String {
+        let mut chain = self.chain.clone();
+        // Partial versions and ranges end up at an installed version
+        if let AliasStatus::Installed(ref version) = self.status {
+            let is_exact_target = chain
+                .last()
+                .and_then(|target| target.parse::<VersionSpec>().ok())
+                .is_some_and(|target| target.version.is_full() && target.version == *version);
+            if !is_exact_target {
+                chain.push(version.to_string());
+            }
+        }
+
+        format!("{} ({})", chain.join(" -> "), self.status_name().replace('_', " "))
+    }
+
+    fn status_name(&self) -> &'static str {
+        match self.status {
+            AliasStatus::Installed(_) => "installed",
+            AliasStatus::NotInstalled(_) => "not_installed",
+            AliasStatus::System => "system",
+            AliasStatus::Dangling => "dangling",
+            AliasStatus::Cyclic => "cyclic",
+            AliasStatus::TooDeep => "too_deep",
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        self.json_value().to_string()
+    }
+
+    fn json_value(&self) -> Value {
+        let version = match self.status {
+            AliasStatus::Installed(ref version) => Some(version.to_string()),
+            AliasStatus::NotInstalled(Some(ref version)) => Some(version.to_string()),
+            _ => None,
+        };
+
+        json!({
+            "name": self.name,
+            "target": self.chain.get(1),
+            "chain": self.chain,
+            "status": self.status_name(),
+            "version": version,
+        })
+    }
+}
@@ -0,0 +1,321 @@
+use std::iter::once;
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use crate::env_utils::Shell;
+use crate::list_remote::RemoteQuery;
+use crate::manage_installs::InstallOptions;
+use crate::misc::Runtime;
+use crate::nvmrc::is_version_like;
+use crate::version::VersionSpec;
+
+/// How the outcome of a command gets printed
+#[derive(Clone, Copy, Default)]
+pub enum Format {
+    /// Code for `--shell` to evaluate, or plain text for
+    /// commands that don't change the environment
+    #[default]
+    Shell,
+    /// A JSON description of the outcome
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "shell" => Ok(Format::Shell),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!("unsupported format '{}'", format)),
+        }
+    }
+}
+
+pub enum Command {
+    /// Switch to the version from the nearest `.nvmrc` (or the
+    /// default version), this is what the shell hooks run
+    Auto,
+    /// Print the hook code that runs quicknvm for a shell
+    Init(Shell),
+    /// List the installed versions, optionally only
+    /// the ones matching a version like `18`
+    Ls(Option<VersionSpec>),
+    /// List the releases available on the mirror
+    LsRemote(RemoteQuery),
+    /// Switch to a version in the current shell and stop switching
+    /// automatically, or go back to switching automatically if
+    /// there's no version
+    Use(Option<String>),
+    /// Install a version, or the one the current directory wants
+    /// if there's no version
+    Install(Option<String>, InstallOptions),
+    /// Remove an installed version, `--force` allows removing
+    /// the current and default versions
+    Uninstall { spec: String, force: bool },
+    /// Show every alias with what it resolves to
+    Aliases,
+    /// Show what a single alias resolves to
+    ShowAlias(String),
+    /// Point an alias to a version
+    SetAlias { name: String, target: String },
+    /// Delete an alias
+    Unalias(String),
+    /// Run a command with a version, or the one the current
+    /// directory wants if there's no version
+    Exec { spec: Option<String>, command: Vec<String> },
+    /// Start a subshell with a version pinned in it, or the one
+    /// the current directory wants if there's no version
+    Shell(Option<String>),
+}
+
+/// Options that only some commands accept, e.g. `--lts` or
+/// `--lts=iron`. Their values always come after a `=`
+struct Flags(Vec<String>);
+
+impl Flags {
+    /// Whether `--name` was passed, its value if it was `--name=VALUE`
+    fn take(&mut self, name: &str) -> Option<Option<String>> {
+        let index = self.0
+            .iter()
+            .position(|flag| flag == name || flag.starts_with(&format!("{}=", name)))?;
+        let flag = self.0.remove(index);
+        Some(flag.split_once('=').map(|(_, value)| value.to_string()))
+    }
+
+    /// Errors on any flags the command didn't use
+    fn finish(self) -> Result<()> {
+        match self.0.first() {
+            Some(flag) => Err(anyhow!("unknown option '{}'", flag)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct Args {
+    pub shell: Shell,
+    pub format: Format,
+    pub command: Command,
+}
+
+/// Parses the command line arguments, excluding the executable name
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args> {
+    let mut shell = Shell::default();
+    let mut format = Format::default();
+    let mut positional = vec![];
+    let mut flags = Flags(vec![]);
+    // Everything after `--`
+    let mut trailing = None;
+
+    while let Some(arg) = args.next() {
+        // Once the command of `exec` or `run` starts, its arguments are
+        // passed on as they are. A `--` right after the version still
+        // separates them, e.g. `run 18 -- --version`
+        let is_command_arg = match positional.first().map(String::as_str) {
+            Some("exec") => positional.len() >= 3,
+            Some("run") => positional.len() >= 3 || (positional.len() == 2 && arg != "--"),
+            _ => false,
+        };
+        // The version is optional, so anything else starts the
+        // command straight away, e.g. `run app.js` or `exec npm test`
+        let is_command_start = matches!(positional.as_slice(), [name] if name == "exec" || name == "run")
+            && !arg.starts_with('-')
+            && !is_version_like(&arg);
+        if is_command_start {
+            trailing = Some(once(arg).chain(args.by_ref()).collect());
+        } else if is_command_arg {
+            positional.push(arg);
+            positional.extend(args.by_ref());
+        } else if arg == "--" {
+            trailing = Some(args.by_ref().collect::<Vec<_>>());
+        } else if arg == "--shell" {
+            shell = args
+                .next()
+                .ok_or(anyhow!("missing value for --shell"))?
+                .parse()?;
+        } else if let Some(value) = arg.strip_prefix("--shell=") {
+            shell = value.parse()?;
+        } else if arg == "--format" {
+            format = args
+                .next()
+                .ok_or(anyhow!("missing value for --format"))?
+                .parse()?;
+        } else if let Some(value) = arg.strip_prefix("--format=") {
+            format = value.parse()?;
+        } else if arg.starts_with("--") {
+            flags.0.push(arg);
+        } else if arg.starts_with('-') {
+            return Err(anyhow!("unknown option '{}'", arg));
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let command = match positional.next().as_deref() {
+        None => Command::Auto,
+        Some("init") => {
+            let shell = positional
+                .next()
+                .ok_or(anyhow!("missing shell for init"))?
+                .parse()?;
+            Command::Init(shell)
+        },
+        Some("ls" | "list") => {
+            let filter = positional
+                .next()
+                .map(|filter| filter.parse())
+                .transpose()
+                .map_err(|_| anyhow!("invalid version to list"))?;
+            Command::Ls(filter)
+        },
+        Some("use") => Command::Use(positional.next()),
+        Some("install" | "i") => {
+            let mut spec = positional.next();
+            if let Some(codename) = flags.take("--lts") {
+                if spec.is_some() {
+                    return Err(anyhow!("--lts can't be used with a version"));
+                }
+                spec = Some(format!("lts/{}", codename.unwrap_or_else(|| "*".to_string())));
+            }
+
+            let options = InstallOptions {
+                latest_npm: flags.take("--latest-npm").is_some(),
+                reinstall_packages_from: flags
+                    .take("--reinstall-packages-from")
+                    .map(|source| source.ok_or(anyhow!("missing value for --reinstall-packages-from")))
+                    .transpose()?,
+            };
+            Command::Install(spec, options)
+        },
+        Some("uninstall") => Command::Uninstall {
+            spec: positional.next().ok_or(anyhow!("missing version to uninstall"))?,
+            force: flags.take("--force").is_some(),
+        },
+        Some("alias") => match (positional.next(), positional.next()) {
+            (None, _) => Command::Aliases,
+            (Some(name), None) => Command::ShowAlias(name),
+            (Some(name), Some(target)) => Command::SetAlias { name, target },
+        },
+        Some("unalias") => Command::Unalias(positional.next().ok_or(anyhow!("missing alias to delete"))?),
+        Some("shell") => Command::Shell(positional.next()),
+        Some(name @ ("exec" | "run")) => {
+            let spec = positional.next();
+            let mut command = trailing.take().unwrap_or_else(|| positional.by_ref().collect());
+            if name == "run" {
+                command.insert(0, "node".to_string());
+            } else if command.is_empty() {
+                return Err(anyhow!("missing command to run"));
+            }
+            Command::Exec { spec, command }
+        },
+        Some("ls-remote" | "list-remote") => {
+            let mut query = match positional.next().as_deref() {
+                None | Some("node") => RemoteQuery::default(),
+                Some("iojs") => RemoteQuery { runtime: Runtime::Iojs, ..Default::default() },
+                Some(filter) => {
+                    let spec: VersionSpec = filter
+                        .parse()
+                        .map_err(|_| anyhow!("invalid version to list"))?;
+                    RemoteQuery {
+                        runtime: spec.runtime(),
+                        version: Some(spec.version),
+                        ..Default::default()
+                    }
+                },
+            };
+            // Plain `--lts` means any LTS release
+            query.lts = flags
+                .take("--lts")
+                .map(|codename| codename.unwrap_or_else(|| "*".to_string()));
+            Command::LsRemote(query)
+        },
+        Some(command) => return Err(anyhow!("unknown command '{}'", command)),
+    };
+
+    if let Some(arg) = positional.next() {
+        return Err(anyhow!("unexpected argument '{}'", arg));
+    }
+    if trailing.is_some() {
+        return Err(anyhow!("unexpected argument '--'"));
+    }
+    flags.finish()?;
+
+    Ok(Args {
+        shell,
+        format,
+        command,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_exec(args: &[&str]) -> (Option<String>, Vec<String>) {
+        let args = parse_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        match args.command {
+            Command::Exec { spec, command } => (spec, command),
+            _ => panic!("not parsed as exec"),
+        }
+    }
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|str| str.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_exec() {
+        let cases: &[(&[&str], Option<&str>, &[&str])] = &[
+            (&["exec", "18", "--", "npm", "test"], Some("18"), &["npm", "test"]),
+            (&["exec", "18", "npm", "test", "--", "--watch"], Some("18"), &["npm", "test", "--", "--watch"]),
+            (&["exec", "--", "node", "-v"], None, &["node", "-v"]),
+            (&["exec", "18", "node", "--version"], Some("18"), &["node", "--version"]),
+            (&["--format", "json", "exec", "18", "--", "node"], Some("18"), &["node"]),
+            (&["exec", "npm", "test"], None, &["npm", "test"]),
+            (&["exec", "npm", "test", "--", "--watch"], None, &["npm", "test", "--", "--watch"]),
+            (&["exec", "iojs-v3", "node"], Some("iojs-v3"), &["node"]),
+            (&["exec", ">=20", "node"], Some(">=20"), &["node"]),
+            (&["exec", "lts/*", "node"], Some("lts/*"), &["node"]),
+        ];
+
+        for &(args, spec, command) in cases {
+            assert_eq!(parse_exec(args), (spec.map(String::from), strings(command)), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn parses_run() {
+        let cases: &[(&[&str], Option<&str>, &[&str])] = &[
+            (&["run", "18", "--", "--version"], Some("18"), &["node", "--version"]),
+            (&["run", "18", "--version"], Some("18"), &["node", "--version"]),
+            (&["run", "18", "app.js", "--", "--port"], Some("18"), &["node", "app.js", "--", "--port"]),
+            (&["run", "--", "app.js"], None, &["node", "app.js"]),
+            (&["run", "lts/iron"], Some("lts/iron"), &["node"]),
+            (&["run", "app.js"], None, &["node", "app.js"]),
+            (&["run", "app.js", "--port", "80"], None, &["node", "app.js", "--port", "80"]),
+            (&["run", "^18.17", "app.js"], Some("^18.17"), &["node", "app.js"]),
+            (&["run", "stable"], Some("stable"), &["node"]),
+        ];
+
+        for &(args, spec, command) in cases {
+            assert_eq!(parse_exec(args), (spec.map(String::from), strings(command)), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn parses_alias() {
+        let parse = |args: &[&str]| parse_args(strings(args).into_iter()).unwrap().command;
+        assert!(matches!(parse(&["alias"]), Command::Aliases));
+        assert!(matches!(parse(&["alias", "work"]), Command::ShowAlias(name) if name == "work"));
+        assert!(matches!(
+            parse(&["alias", "work", "lts/iron"]),
+            Command::SetAlias { name, target } if name == "work" && target == "lts/iron"
+        ));
+    }
+
+    #[test]
+    fn rejects_exec_without_a_command() {
+        assert!(parse_args(strings(&["exec", "18"]).into_iter()).is_err());
+        assert!(parse_args(strings(&["exec", "18", "--"]).into_iter()).is_err());
+    }
+}
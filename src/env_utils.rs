@@ -1,11 +1,44 @@
-use crate::{version::Version, misc::DOT_NVM_HOME};
+use std::str::FromStr;
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use crate::{version::Version, misc::NVM_DIR};
 
-#[derive(Debug)]
+/// The shell that generated scripts are meant to be evaluated by
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Shell {
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+    Nu,
+}
+
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(shell: &str) -> Result<Self, Self::Err> {
+        match shell {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "elvish" => Ok(Shell::Elvish),
+            "nu" | "nushell" => Ok(Shell::Nu),
+            _ => Err(anyhow!("unsupported shell '{}'", shell)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "action")]
 pub enum EnvChangeset {
+    #[serde(rename = "set")]
     UpdateVar {
         name: String,
         value: String,
     },
+    #[serde(rename = "unset")]
     DeleteVar {
         name: String,
     },
@@ -15,12 +48,17 @@ pub fn get_new_env(version: &Version, env: &str, append_path: &str) -> String {
     format!("{}{}:{}", version.location.as_ref().unwrap().to_str().unwrap(), append_path, env)
 }
 
+/// Checks if a path is inside of `NVM_DIR`
+pub fn is_nvm_path(path: &str) -> bool {
+    path.strip_prefix(NVM_DIR.as_str())
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
 /// Remove any found NVM paths from a PATH env var string
 pub fn strip_nvm_path(env_var: &str) -> String {
-    let nvm_path = &DOT_NVM_HOME[..DOT_NVM_HOME.len() - 1];
     let stripped_env_var = env_var
         .split(":")
-        .filter(|path| !path.starts_with(nvm_path))
+        .filter(|path| !is_nvm_path(path))
         .collect::<Vec<_>>()
         .join(":");
     stripped_env_var
@@ -28,43 +66,179 @@ pub fn strip_nvm_path(env_var: &str) -> String {
 
 /// Generates a small shell script that the calling
 /// shell can just `eval` to update its environment
-pub fn gen_shell_script(changesets: &Vec<EnvChangeset>) -> String {
-    let separator = "\n";
-    let script = changesets
-        .into_iter()
-        .map(|changset| match changset {
-            EnvChangeset::UpdateVar {
-                name,
-                value,
-            } => {
-                format!("export {}={};", name, sanitize_shell_value(&value))
-            },
-            EnvChangeset::DeleteVar { name } => {
-                format!("unset {};", name)
+pub fn gen_shell_script(changesets: &[EnvChangeset], shell: Shell) -> String {
+    let commands: Vec<String> = match shell {
+        // Nushell can't `eval`, see `gen_nu_record`
+        Shell::Nu => return gen_nu_record(changesets),
+        Shell::Bash | Shell::Zsh => changesets
+            .iter()
+            .map(|changeset| match changeset {
+                EnvChangeset::UpdateVar { name, value } => {
+                    format!("export {}={};", name, sanitize_shell_value(value, shell))
+                },
+                EnvChangeset::DeleteVar { name } => format!("unset {};", name),
+            })
+            .collect(),
+        Shell::Fish => changesets
+            .iter()
+            .map(|changeset| match changeset {
+                // Fish keeps PATH-like variables as lists, so split
+                // them up instead of setting a single element
+                EnvChangeset::UpdateVar { name, value } if name.ends_with("PATH") => {
+                    format!("set -gx {} (string split -- ':' {});", name, sanitize_shell_value(value, shell))
+                },
+                EnvChangeset::UpdateVar { name, value } => {
+                    format!("set -gx {} {};", name, sanitize_shell_value(value, shell))
+                },
+                EnvChangeset::DeleteVar { name } => format!("set -e {};", name),
+            })
+            .collect(),
+        Shell::Elvish => changesets
+            .iter()
+            .map(|changeset| match changeset {
+                EnvChangeset::UpdateVar { name, value } => {
+                    format!("set-env {} {}", name, sanitize_shell_value(value, shell))
+                },
+                EnvChangeset::DeleteVar { name } => format!("unset-env {}", name),
+            })
+            .collect(),
+    };
+
+    commands.join("\n")
+}
+
+/// Nushell can't `eval` anything, so it gets a record instead
+/// (JSON, which is also valid NUON) that its hook passes to
+/// `load-env` and `hide-env`, e.g.
+/// `{"set":{"PATH":"...","NVM_BIN":"..."},"hide":["NVM_INC"]}`
+fn gen_nu_record(changesets: &[EnvChangeset]) -> String {
+    let mut set = Map::new();
+    let mut hide = vec![];
+
+    for changeset in changesets {
+        match changeset {
+            EnvChangeset::UpdateVar { name, value } => {
+                set.insert(name.clone(), Value::String(value.clone()));
             },
-        })
-        .fold(String::new(), |mut acc, item| {
-            acc.push_str(separator);
-            acc.push_str(&item);
-            acc
-        });
-
-    script
+            EnvChangeset::DeleteVar { name } => hide.push(Value::String(name.clone())),
+        }
+    }
+
+    json!({ "set": set, "hide": hide }).to_string()
 }
 
-pub fn sanitize_shell_value(value: &str) -> String {
+/// Quotes a value so that it's passed verbatim to `shell`
+pub fn sanitize_shell_value(value: &str, shell: Shell) -> String {
     let mut sanitized_inner_string = String::new();
-    for chr in value.chars() {
-        match chr {
-            '\'' => sanitized_inner_string.push_str("'\'$'"),
-            '\r' => sanitized_inner_string.push_str("\\r"),
-            '\n' => sanitized_inner_string.push_str("\\n"),
-            '\\' => sanitized_inner_string.push_str("\\\\"),
-            '\0' => {}, // we ignore null characters
-            '\x1b' => sanitized_inner_string.push_str("\\e"),
-            _ => sanitized_inner_string.push(chr),
-        }
+
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            for chr in value.chars() {
+                match chr {
+                    '\'' => sanitized_inner_string.push_str("\\'"),
+                    '\r' => sanitized_inner_string.push_str("\\r"),
+                    '\n' => sanitized_inner_string.push_str("\\n"),
+                    '\\' => sanitized_inner_string.push_str("\\\\"),
+                    '\0' => {}, // we ignore null characters
+                    '\x1b' => sanitized_inner_string.push_str("\\e"),
+                    _ => sanitized_inner_string.push(chr),
+                }
+            }
+
+            format!("$'{}'", sanitized_inner_string)
+        },
+        Shell::Fish => {
+            // Only backslashes and single quotes are special
+            // inside of single quotes in fish
+            for chr in value.chars() {
+                match chr {
+                    '\'' => sanitized_inner_string.push_str("\\'"),
+                    '\\' => sanitized_inner_string.push_str("\\\\"),
+                    '\0' => {}, // fish can't represent null characters either
+                    _ => sanitized_inner_string.push(chr),
+                }
+            }
+
+            format!("'{}'", sanitized_inner_string)
+        },
+        // Values end up inside of JSON strings, see `gen_nu_record`
+        Shell::Nu => Value::String(value.to_string()).to_string(),
+        Shell::Elvish => {
+            // Elvish single quoted strings are completely raw
+            // apart from '' which is a literal single quote
+            for chr in value.chars() {
+                match chr {
+                    '\'' => sanitized_inner_string.push_str("''"),
+                    '\0' => {},
+                    _ => sanitized_inner_string.push(chr),
+                }
+            }
+
+            format!("'{}'", sanitized_inner_string)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values that could break out of the quotes if they weren't escaped
+    const HOSTILE_VALUES: [&str; 5] = [
+        "/it's/bin",
+        "/back\\slash/bin",
+        "/new\nline/bin",
+        "/nul\0byte/bin",
+        "'; rm -rf ~; echo '",
+    ];
+
+    fn sanitize_all(shell: Shell) -> Vec<String> {
+        HOSTILE_VALUES
+            .iter()
+            .map(|value| sanitize_shell_value(value, shell))
+            .collect()
     }
 
-    format!("$'{}'", sanitized_inner_string)
+    #[test]
+    fn quotes_for_bash_and_zsh() {
+        let expected = [
+            r"$'/it\'s/bin'",
+            r"$'/back\\slash/bin'",
+            r"$'/new\nline/bin'",
+            r"$'/nulbyte/bin'",
+            r"$'\'; rm -rf ~; echo \''",
+        ];
+        assert_eq!(sanitize_all(Shell::Bash), expected);
+        assert_eq!(sanitize_all(Shell::Zsh), expected);
+    }
+
+    #[test]
+    fn quotes_for_fish() {
+        assert_eq!(sanitize_all(Shell::Fish), [
+            r"'/it\'s/bin'",
+            r"'/back\\slash/bin'",
+            "'/new\nline/bin'",
+            "'/nulbyte/bin'",
+            r"'\'; rm -rf ~; echo \''",
+        ]);
+    }
+
+    #[test]
+    fn quotes_for_elvish() {
+        assert_eq!(sanitize_all(Shell::Elvish), [
+            "'/it''s/bin'",
+            r"'/back\slash/bin'",
+            "'/new\nline/bin'",
+            "'/nulbyte/bin'",
+            "'''; rm -rf ~; echo '''",
+        ]);
+    }
+
+    #[test]
+    fn quotes_for_nu() {
+        for value in HOSTILE_VALUES {
+            let quoted = sanitize_shell_value(value, Shell::Nu);
+            assert_eq!(serde_json::from_str::<String>(&quoted).unwrap(), value);
+        }
+    }
 }
@@ -0,0 +1,41 @@
+use std::env::var as get_env_var;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use anyhow::anyhow;
+use crate::env_utils::EnvChangeset;
+
+/// Replaces quicknvm with a command that has the changes applied to its
+/// environment, so signals and the exit status go straight to and from
+/// it. Only returns if the command couldn't be started
+pub fn exec_with_changes(command: &[String], changesets: &[EnvChangeset]) -> anyhow::Error {
+    let (program, args) = match command.split_first() {
+        Some(command) => command,
+        None => return anyhow!("missing command to run"),
+    };
+    let mut child_command = Command::new(program);
+    child_command.args(args);
+    apply_changes(&mut child_command, changesets);
+
+    let err = child_command.exec();
+    anyhow!("couldn't run {}: {}", program, err)
+}
+
+/// Replaces quicknvm with a new `$SHELL` that has the changes applied
+/// to its environment. Only returns if the shell couldn't be started
+pub fn exec_shell(changesets: &[EnvChangeset]) -> anyhow::Error {
+    let shell = get_env_var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    let mut shell_command = Command::new(&shell);
+    apply_changes(&mut shell_command, changesets);
+
+    let err = shell_command.exec();
+    anyhow!("couldn't start {}: {}", shell, err)
+}
+
+fn apply_changes(command: &mut Command, changesets: &[EnvChangeset]) {
+    for changeset in changesets {
+        match changeset {
+            EnvChangeset::UpdateVar { name, value } => command.env(name, value),
+            EnvChangeset::DeleteVar { name } => command.env_remove(name),
+        };
+    }
+}
@@ -0,0 +1,214 @@
+use std::env::var as get_env_var;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use tokio::fs;
+use crate::misc::{nvm_path, Runtime, QUICKNVM_CACHE_DIR};
+use crate::mirror::{fetch_string, try_mirrors, Channel};
+use crate::nvmrc::LTS_STR_START;
+use crate::version::{Version, VersionSpec};
+use crate::version_req::VersionReq;
+
+const DEFAULT_INDEX_TTL: u64 = 60 * 60;
+
+/// A single entry of a mirror's `index.json`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Release {
+    pub version: String,
+    pub date: String,
+    #[serde(default)]
+    pub files: Vec<String>,
+    pub npm: Option<String>,
+    /// The LTS codename, the index has `false` for non-LTS releases
+    #[serde(deserialize_with = "deserialize_lts", serialize_with = "serialize_lts")]
+    pub lts: Option<String>,
+    #[serde(default)]
+    pub security: bool,
+}
+
+fn deserialize_lts<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(codename) => Some(codename),
+        _ => None,
+    })
+}
+
+fn serialize_lts<S: Serializer>(lts: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match lts {
+        Some(codename) => serializer.serialize_str(codename),
+        None => serializer.serialize_bool(false),
+    }
+}
+
+impl Release {
+    pub fn parsed_version(&self) -> Option<Version> {
+        self.version.parse().ok()
+    }
+}
+
+/// How long a cached index is used before it's fetched again, can
+/// be changed with `QUICKNVM_INDEX_TTL` (in seconds)
+fn index_ttl() -> Duration {
+    let seconds = get_env_var("QUICKNVM_INDEX_TTL")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(DEFAULT_INDEX_TTL);
+    Duration::from_secs(seconds)
+}
+
+/// Gets the list of releases from the mirror's `index.json`, newest
+/// first. The index is cached in `NVM_DIR` and the cached copy is
+/// used if it's fresh enough, or if the mirror can't be reached
+pub async fn fetch_index(runtime: Runtime, channel: Channel) -> Result<Vec<Release>> {
+    let cache_dir = nvm_path(QUICKNVM_CACHE_DIR);
+    let cache_path = index_cache_path(runtime, channel);
+
+    let cache_age = fs::metadata(&cache_path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    if cache_age.is_some_and(|age| age < index_ttl()) {
+        if let Ok(releases) = read_index(&cache_path).await {
+            return Ok(releases);
+        }
+    }
+
+    let index = try_mirrors(runtime, channel, |mirror| async move {
+        let url = format!("{}/index.json", mirror);
+        let index = fetch_string(&url).await?;
+        let releases: Vec<Release> = serde_json::from_str(&index)
+            .with_context(|| format!("invalid index {}", url))?;
+        Ok(releases)
+    }).await;
+    let mut releases = match index {
+        Ok(releases) => releases,
+        Err(err) => {
+            // Offline, so a stale index is better than nothing
+            return read_index(&cache_path)
+                .await
+                .map_err(|_| err);
+        },
+    };
+    sort_releases(&mut releases);
+
+    if fs::create_dir_all(&cache_dir).await.is_ok() {
+        let _ = fs::write(&cache_path, serde_json::to_string(&releases)?).await;
+    }
+
+    Ok(releases)
+}
+
+fn index_cache_path(runtime: Runtime, channel: Channel) -> String {
+    let cache_dir = nvm_path(QUICKNVM_CACHE_DIR);
+    match channel {
+        Channel::Release => format!("{}/{}-index.json", cache_dir, runtime.artifact_prefix()),
+        _ => format!("{}/{}-{}-index.json", cache_dir, runtime.artifact_prefix(), channel.name()),
+    }
+}
+
+/// The cached index regardless of its age, without ever going
+/// to the mirror. None if the index was never fetched
+pub async fn cached_index(runtime: Runtime, channel: Channel) -> Option<Vec<Release>> {
+    read_index(&index_cache_path(runtime, channel)).await.ok()
+}
+
+async fn read_index(path: &str) -> Result<Vec<Release>> {
+    let mut releases: Vec<Release> = serde_json::from_str(&fs::read_to_string(path).await?)?;
+    sort_releases(&mut releases);
+    Ok(releases)
+}
+
+fn sort_releases(releases: &mut Vec<Release>) {
+    releases.retain(|release| release.parsed_version().is_some());
+    releases.sort_by(|a, b| {
+        let a = a.parsed_version().unwrap();
+        let b = b.parsed_version().unwrap();
+        b.cmp(&a)
+    });
+}
+
+/// Finds the newest release matching an nvm version string, which
+/// can be an exact or partial version (optionally prefixed with its
+/// runtime like `iojs-v3`), `node`, `stable`, `unstable`, `iojs`,
+/// `lts/*`, `lts/CODENAME`, `lts/-N`, `rc`, `nightly` or an npm-style
+/// range. `rc` and `nightly` only make sense with those channels' index
+pub fn find_release<'a>(releases: &'a [Release], spec: &str) -> Option<&'a Release> {
+    let spec = spec.trim();
+
+    if let Some(lts_name) = spec.strip_prefix(LTS_STR_START) {
+        let mut lts_releases = releases.iter().filter(|release| release.lts.is_some());
+
+        return match lts_name {
+            "*" => lts_releases.next(),
+            _ if lts_name.starts_with('-') => {
+                let offset: usize = lts_name[1..].parse().ok()?;
+                let mut codenames: Vec<&str> = vec![];
+                for release in releases.iter() {
+                    if let Some(ref codename) = release.lts {
+                        if !codenames.contains(&codename.as_str()) {
+                            codenames.push(codename);
+                        }
+                    }
+                }
+                let codename = codenames.get(offset)?;
+                lts_releases.find(|release| release.lts.as_deref() == Some(*codename))
+            },
+            _ => lts_releases.find(|release| release
+                .lts
+                .as_ref()
+                .is_some_and(|codename| codename.eq_ignore_ascii_case(lts_name))),
+        };
+    }
+
+    match spec {
+        "iojs" | "rc" | "nightly" => releases.first(),
+        "node" | "stable" => releases
+            .iter()
+            .find(|release| release
+                .parsed_version()
+                .is_some_and(|version| version.pre.is_none() && version.is_stable())),
+        "unstable" => releases
+            .iter()
+            .find(|release| release
+                .parsed_version()
+                .is_some_and(|version| version.pre.is_none() && !version.is_stable())),
+        _ => {
+            if let Ok(VersionSpec { version, .. }) = spec.parse() {
+                return releases
+                    .iter()
+                    .find(|release| release
+                        .parsed_version()
+                        .is_some_and(|release_version| version.matches(&release_version)));
+            }
+
+            // Maybe it's a range from package.json's engines
+            let version_req: VersionReq = spec.parse().ok()?;
+            releases
+                .iter()
+                .find(|release| release
+                    .parsed_version()
+                    .is_some_and(|release_version| version_req.matches(&release_version)))
+        },
+    }
+}
+
+/// Resolves a version string to the newest matching release
+/// available on the mirror
+pub async fn resolve_remote_version(spec: &str) -> Result<Version> {
+    let (runtime, channel) = match spec.trim() {
+        "iojs" => (Runtime::Iojs, Channel::Release),
+        "rc" => (Runtime::Node, Channel::Rc),
+        "nightly" => (Runtime::Node, Channel::Nightly),
+        spec => spec
+            .parse::<VersionSpec>()
+            .map(|spec| (spec.runtime(), Channel::of(&spec.version)))
+            .unwrap_or((Runtime::Node, Channel::Release)),
+    };
+    let releases = fetch_index(runtime, channel).await?;
+
+    find_release(&releases, spec)
+        .and_then(|release| release.parsed_version())
+        .ok_or(anyhow!("no release matching '{}' found", spec.trim()))
+}
@@ -0,0 +1,100 @@
+use crate::env_utils::Shell;
+
+const BASH_HOOK: &str = r#"__quicknvm_load() {
+  if [[ "$PWD" != "${__QUICKNVM_LAST_PWD-}" ]]; then
+    __QUICKNVM_LAST_PWD="$PWD"
+    eval "$(command quicknvm --shell bash)"
+  fi
+}
+if [[ ";${PROMPT_COMMAND-};" != *";__quicknvm_load;"* ]]; then
+  PROMPT_COMMAND="__quicknvm_load${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+quicknvm() {
+  if [[ "${1-}" == use ]]; then
+    local changes
+    changes="$(command quicknvm --shell bash "$@")" || return
+    eval "$changes"
+  else
+    command quicknvm "$@"
+  fi
+}"#;
+
+const ZSH_HOOK: &str = r#"autoload -U add-zsh-hook
+__quicknvm_load() {
+  eval "$(command quicknvm --shell zsh)"
+}
+add-zsh-hook chpwd __quicknvm_load
+quicknvm() {
+  if [[ "${1-}" == use ]]; then
+    local changes
+    changes="$(command quicknvm --shell zsh "$@")" || return
+    eval "$changes"
+  else
+    command quicknvm "$@"
+  fi
+}
+__quicknvm_load"#;
+
+const FISH_HOOK: &str = r#"function __quicknvm_load --on-variable PWD --description 'Switch Node.js versions with quicknvm'
+    status --is-command-substitution; and return
+    command quicknvm --shell fish | source
+end
+function quicknvm
+    if test "$argv[1]" = use
+        set -l changes (command quicknvm --shell fish $argv); or return
+        string join \n -- $changes | source
+    else
+        command quicknvm $argv
+    end
+end
+__quicknvm_load"#;
+
+const ELVISH_HOOK: &str = r#"set after-chdir = [$@after-chdir {|_| eval (e:quicknvm --shell elvish | slurp) }]
+fn quicknvm {|@args|
+  if (and (> (count $args) 0) (eq $args[0] use)) {
+    eval (e:quicknvm --shell elvish $@args | slurp)
+  } else {
+    e:quicknvm $@args
+  }
+}
+eval (e:quicknvm --shell elvish | slurp)"#;
+
+const NU_HOOK: &str = r#"def --env __quicknvm_apply [changes: string] {
+    let changes = ($changes | str trim)
+    if ($changes | is-empty) { return }
+    let changes = ($changes | from json)
+    hide-env --ignore-errors ...$changes.hide
+    let vars = if 'PATH' in $changes.set {
+        $changes.set | upsert PATH {|vars| $vars.PATH | split row (char esep) }
+    } else {
+        $changes.set
+    }
+    load-env $vars
+}
+def --env __quicknvm_load [] {
+    __quicknvm_apply (^quicknvm --shell nu)
+}
+def --env --wrapped quicknvm [...args] {
+    if ($args | get 0? | default '') == 'use' {
+        __quicknvm_apply (^quicknvm --shell nu ...$args)
+    } else {
+        ^quicknvm ...$args
+    }
+}
+$env.config.hooks.env_change.PWD = (
+    $env.config.hooks.env_change.PWD? | default [] | append {|before, after| __quicknvm_load }
+)
+__quicknvm_load"#;
+
+/// Generates the code a shell needs in its rc file to run
+/// quicknvm whenever the working directory changes, along with
+/// a `quicknvm` wrapper that applies `quicknvm use` to the shell
+pub fn gen_init_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH_HOOK,
+        Shell::Zsh => ZSH_HOOK,
+        Shell::Fish => FISH_HOOK,
+        Shell::Elvish => ELVISH_HOOK,
+        Shell::Nu => NU_HOOK,
+    }
+}
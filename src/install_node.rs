@@ -1,34 +1,154 @@
-use std::process::Stdio;
-use anyhow::{anyhow, Result};
-use tokio::process::Command;
-use crate::nvmrc::resolve_nvmrc_version;
-use crate::version::{Version, NodeVersion};
-use crate::misc::DOT_NVM_HOME;
+use std::env::consts::{ARCH, OS};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use anyhow::{anyhow, Context, Result};
+use tokio::fs;
+use tokio::task::spawn_blocking;
+use flate2::read::GzDecoder;
+use tar::Archive;
+use xz2::read::XzDecoder;
+use crate::index::resolve_remote_version;
+use crate::version::{BrokenInstall, Version, VersionSpec, find_version_of};
+use crate::misc::{is_failed_extract, nvm_path, Runtime, QUICKNVM_CACHE_DIR};
+use crate::mirror::{download, try_mirrors, Channel};
+use crate::verify::{fetch_shasums, is_listed, verify_artifact};
 
+/// Finds the newest release matching an nvm version string
+/// and which runtime released it
+pub async fn resolve_release(version: &str) -> Result<(Version, Runtime)> {
+    match version.parse::<VersionSpec>() {
+        // Exact versions don't need the index
+        Ok(spec) if spec.version.is_full() => Ok((spec.version.clone(), spec.runtime())),
+        _ => {
+            let release_version = resolve_remote_version(version).await?;
+            let runtime = Runtime::of(&release_version);
+            Ok((release_version, runtime))
+        },
+    }
+}
+
+/// Installs the newest release matching an nvm version string, or
+/// returns the installed version if it's already there
 pub async fn install_node(version: &str) -> Result<Version> {
-    let nvm_script = format!("{}/nvm.sh", DOT_NVM_HOME.as_str());
-    let install_script = r#"
-        source "$1";
-        nvm install "$2";
-    "#;
-    let install_command = Command::new("bash")
-        .args(["-c", install_script, "--", nvm_script.as_str(), version])
-        .stdout(Stdio::null())
-        .status()
-        .await;
-
-    if let Ok(status) = install_command {
-        if status.success() {
-            let downloaded_version = resolve_nvmrc_version(version, 0).await?;
-
-            match downloaded_version {
-                NodeVersion::NvmVersion(Some(version)) => Ok(version),
-                _ => Err(anyhow!("unknown error installed '{}'", version))
+    let (release_version, runtime) = resolve_release(version).await?;
+
+    match find_version_of(&release_version, runtime.into()).await {
+        Ok(installed_version) => return Ok(installed_version),
+        // Reinstall over the remains of an aborted install, anything
+        // else is left for an explicit `quicknvm install` to replace
+        Err(err) => if let Some(broken_install) = err.downcast_ref::<BrokenInstall>() {
+            let location = broken_install.version.location.as_ref().unwrap();
+            if !is_failed_extract(location).await {
+                return Err(anyhow!(
+                    "{}, run `quicknvm install {}` to reinstall it",
+                    broken_install,
+                    broken_install.version,
+                ));
             }
-        } else {
-            Err(anyhow!("version might not exist"))
-        }
-    } else {
-        Err(anyhow!("failed to run `nvm install`"))
+
+            eprintln!("{}, reinstalling it", broken_install);
+            remove_broken_install(broken_install).await?;
+        },
+    }
+
+    install_version(&release_version, runtime).await
+}
+
+/// Removes a broken install's directory so it can be installed again
+pub async fn remove_broken_install(broken_install: &BrokenInstall) -> Result<()> {
+    let location = broken_install.version.location.as_ref().unwrap();
+    fs::remove_dir_all(location)
+        .await
+        .with_context(|| format!("couldn't remove {}", location.display()))
+}
+
+/// Downloads a release tarball from the mirror and unpacks it into
+/// the same directory `nvm install` would, so both tools can use it
+pub async fn install_version(version: &Version, runtime: Runtime) -> Result<Version> {
+    if !version.is_full() {
+        return Err(anyhow!("can't install partial version {}", version));
     }
+
+    let artifact = format!("{}-{}-{}", runtime.artifact_prefix(), version, get_platform()?);
+
+    // Everything is downloaded and unpacked in quicknvm's own cache
+    // first so that a failed install never leaves a half-extracted
+    // version behind. nvm's download cache in `.cache/bin` is left alone
+    let cache_dir = PathBuf::from(nvm_path(QUICKNVM_CACHE_DIR)).join(format!("tmp-{}", process::id()));
+    if fs::metadata(&cache_dir).await.is_ok() {
+        fs::remove_dir_all(&cache_dir).await?;
+    }
+    fs::create_dir_all(&cache_dir)
+        .await
+        .with_context(|| format!("couldn't create {}", cache_dir.display()))?;
+
+    let install_dir = PathBuf::from(format!("{}/{}", nvm_path(runtime.install_dir()), version));
+    let result = try_mirrors(runtime, Channel::of(version), |mirror| {
+        let release_url = format!("{}/{}", mirror, version);
+        let (cache_dir, artifact, install_dir) = (&cache_dir, &artifact, &install_dir);
+        async move { download_release(&release_url, cache_dir, artifact, install_dir).await }
+    }).await;
+    let _ = fs::remove_dir_all(&cache_dir).await;
+    result?;
+
+    let mut installed_version = version.clone();
+    installed_version.location = Some(install_dir);
+    Ok(installed_version)
+}
+
+async fn download_release(release_url: &str, cache_dir: &Path, artifact: &str, install_dir: &Path) -> Result<()> {
+    let shasums = fetch_shasums(release_url, cache_dir).await?;
+    // Old releases (and io.js on darwin before 2.3.2) were only
+    // published as gzipped tarballs
+    let xz_name = format!("{}.tar.xz", artifact);
+    let tarball_name = if is_listed(&shasums, &xz_name) {
+        xz_name
+    } else {
+        format!("{}.tar.gz", artifact)
+    };
+    let tarball_path = cache_dir.join(&tarball_name);
+    download(&format!("{}/{}", release_url, tarball_name), &tarball_path).await?;
+    verify_artifact(&shasums, &tarball_path).await?;
+
+    let unpack_dir = cache_dir.join("files");
+    let unpack_dir_clone = unpack_dir.clone();
+    let tarball = std::fs::File::open(&tarball_path)?;
+    let decoder: Box<dyn Read + Send> = if tarball_name.ends_with(".xz") {
+        Box::new(XzDecoder::new(tarball))
+    } else {
+        Box::new(GzDecoder::new(tarball))
+    };
+    spawn_blocking(move || Archive::new(decoder).unpack(unpack_dir_clone))
+        .await?
+        .with_context(|| format!("couldn't unpack {}", tarball_name))?;
+
+    fs::create_dir_all(install_dir.parent().unwrap()).await?;
+    fs::rename(unpack_dir.join(artifact), install_dir)
+        .await
+        .with_context(|| format!("couldn't move {} into {}", artifact, install_dir.display()))?;
+
+    Ok(())
+}
+
+/// The `OS-ARCH` part of release artifact names
+fn get_platform() -> Result<String> {
+    let os = match OS {
+        "linux" => "linux",
+        "macos" => "darwin",
+        "aix" => "aix",
+        _ => return Err(anyhow!("no releases for {}", OS)),
+    };
+    let arch = match ARCH {
+        "x86_64" => "x64",
+        "x86" => "x86",
+        "aarch64" => "arm64",
+        "arm" => "armv7l",
+        "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+        "powerpc64" => "ppc64",
+        "s390x" => "s390x",
+        _ => return Err(anyhow!("no releases for {}", ARCH)),
+    };
+
+    Ok(format!("{}-{}", os, arch))
 }
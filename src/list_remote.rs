@@ -0,0 +1,142 @@
+use anyhow::Result;
+use serde_json::json;
+use crate::index::{fetch_index, Release};
+use crate::mirror::Channel;
+use crate::misc::{list_all_nvm_versions, Runtime, PATH};
+use crate::version::{Version, VersionSpec, find_current_version};
+
+/// Which releases `quicknvm ls-remote` shows
+#[derive(Default)]
+pub struct RemoteQuery {
+    pub runtime: Runtime,
+    /// Only releases matching a partial version like `18`
+    pub version: Option<Version>,
+    /// Only LTS releases with this codename, `*` for any LTS
+    pub lts: Option<String>,
+}
+
+/// A release from the index and how it relates to what's installed
+pub struct RemoteRelease {
+    pub release: Release,
+    pub version: Version,
+    /// The newest release of its LTS line
+    pub latest_lts: bool,
+    pub installed: bool,
+    pub current: bool,
+}
+
+pub struct RemoteListing {
+    pub runtime: Runtime,
+    /// Oldest first like `nvm ls-remote`
+    pub releases: Vec<RemoteRelease>,
+}
+
+/// Lists the mirror's releases, using the cached index while it's
+/// fresh or when the mirror can't be reached
+pub async fn list_remote(query: &RemoteQuery) -> Result<RemoteListing> {
+    let index = fetch_index(query.runtime, Channel::Release).await?;
+    let installed = list_all_nvm_versions(query.runtime.into()).await?;
+    let current = find_current_version(PATH.as_str());
+
+    let mut seen_lts_lines: Vec<&str> = vec![];
+    let mut releases = vec![];
+    // The index is sorted newest first, so the first
+    // release of an LTS line is the latest
+    for release in index.iter() {
+        let version = match release.parsed_version() {
+            Some(version) => version,
+            None => continue,
+        };
+        let latest_lts = match release.lts {
+            Some(ref codename) if !seen_lts_lines.contains(&codename.as_str()) => {
+                seen_lts_lines.push(codename);
+                true
+            },
+            _ => false,
+        };
+
+        let matches_version = query
+            .version
+            .as_ref()
+            .is_none_or(|query_version| query_version.matches(&version));
+        let matches_lts = match (query.lts.as_deref(), release.lts.as_deref()) {
+            (None, _) => true,
+            (Some("*"), Some(_)) => true,
+            (Some(query_codename), Some(codename)) => query_codename.eq_ignore_ascii_case(codename),
+            (Some(_), None) => false,
+        };
+        if !matches_version || !matches_lts {
+            continue;
+        }
+
+        releases.push(RemoteRelease {
+            release: release.clone(),
+            installed: installed.contains(&version),
+            current: current.as_ref() == Some(&version),
+            version,
+            latest_lts,
+        });
+    }
+    releases.reverse();
+
+    Ok(RemoteListing {
+        runtime: query.runtime,
+        releases,
+    })
+}
+
+impl RemoteListing {
+    /// The same layout as `nvm ls-remote`, the current version is
+    /// marked with `->` and installed versions with `*`
+    pub fn to_text(&self) -> String {
+        if self.releases.is_empty() {
+            return format!("{:>18}", "N/A");
+        }
+
+        self.releases
+            .iter()
+            .map(|remote| {
+                let name = VersionSpec { runtime: Some(self.runtime), version: remote.version.clone() };
+                let mut line = format!(
+                    "{:>2} {:>15}{}",
+                    if remote.current { "->" } else { "" },
+                    name.to_string(),
+                    if remote.installed { " *" } else { "  " },
+                );
+
+                let mut tags = vec![];
+                if let Some(ref codename) = remote.release.lts {
+                    let latest = if remote.latest_lts { "Latest " } else { "" };
+                    tags.push(format!("{}LTS: {}", latest, codename));
+                }
+                if remote.release.security {
+                    tags.push("security".to_string());
+                }
+                if !tags.is_empty() {
+                    line.push_str(&format!("   ({})", tags.join(", ")));
+                }
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let releases = self.releases
+            .iter()
+            .map(|remote| json!({
+                "version": remote.version.to_string(),
+                "runtime": self.runtime.name(),
+                "date": remote.release.date,
+                "npm": remote.release.npm,
+                "lts": remote.release.lts,
+                "latest_lts": remote.latest_lts,
+                "security": remote.release.security,
+                "installed": remote.installed,
+                "current": remote.current,
+            }))
+            .collect::<Vec<_>>();
+
+        json!(releases).to_string()
+    }
+}
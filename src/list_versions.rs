@@ -0,0 +1,203 @@
+use anyhow::Result;
+use serde_json::json;
+use crate::alias::{list_aliases, alias_chain, resolve_alias_chain};
+use crate::index::cached_index;
+use crate::mirror::Channel;
+use crate::misc::{list_all_nvm_versions, list_broken_nvm_versions, Runtime, PATH};
+use crate::query_current::system_node_version;
+use crate::version::{Version, VersionSpec, find_current_version};
+
+/// An installed version and everything that points to it
+pub struct InstalledVersion {
+    pub version: Version,
+    pub runtime: Runtime,
+    /// The aliases that end up at this version, e.g. `default` or `lts/iron`
+    pub aliases: Vec<String>,
+    /// The LTS codename, only known if the index was fetched before
+    pub lts: Option<String>,
+    pub current: bool,
+    pub default: bool,
+    /// Has no executable `bin/node`, see `BrokenInstall`
+    pub broken: bool,
+}
+
+/// An alias and what it ends up pointing to
+pub struct AliasListing {
+    pub name: String,
+    pub target: String,
+    /// Every alias followed starting with this one, see `alias_chain`
+    pub chain: Vec<String>,
+    /// None if the alias doesn't point to an installed version
+    pub version: Option<Version>,
+}
+
+/// Everything `quicknvm ls` shows
+pub struct Listing {
+    pub versions: Vec<InstalledVersion>,
+    /// Empty when the listing is filtered
+    pub aliases: Vec<AliasListing>,
+    pub system: Option<Version>,
+    /// Whether the system version is the one in use
+    pub system_current: bool,
+}
+
+/// Lists the installed versions, or only the ones matching `filter`
+/// (e.g. `18` or `iojs-3`), without going to the mirror
+pub async fn list_versions(filter: Option<&VersionSpec>) -> Result<Listing> {
+    let current = find_current_version(PATH.as_str());
+    let default = resolve_alias_chain(&alias_chain("default").await).await;
+
+    let mut aliases = vec![];
+    for alias in list_aliases().await? {
+        let chain = alias_chain(&alias.name).await;
+        let version = resolve_alias_chain(&chain).await;
+        aliases.push(AliasListing {
+            name: alias.name,
+            target: alias.target,
+            chain,
+            version,
+        });
+    }
+
+    let mut versions = vec![];
+    for runtime in [Runtime::Node, Runtime::Iojs] {
+        let releases = cached_index(runtime, Channel::Release).await.unwrap_or_default();
+
+        let installed = list_all_nvm_versions(runtime.into()).await?;
+        let broken = list_broken_nvm_versions(runtime.into()).await?;
+        let all_versions = installed
+            .into_iter()
+            .map(|version| (version, false))
+            .chain(broken.into_iter().map(|version| (version, true)));
+
+        for (version, broken) in all_versions {
+            let is_match = filter.is_none_or(|filter| {
+                filter.runtime.is_none_or(|filter_runtime| filter_runtime == runtime)
+                    && filter.version.matches(&version)
+            });
+            if !is_match {
+                continue;
+            }
+
+            let lts = releases
+                .iter()
+                .find(|release| release.parsed_version().as_ref() == Some(&version))
+                .and_then(|release| release.lts.clone());
+            versions.push(InstalledVersion {
+                runtime,
+                aliases: aliases
+                    .iter()
+                    .filter(|alias| alias.version.as_ref() == Some(&version))
+                    .map(|alias| alias.name.clone())
+                    .collect(),
+                lts,
+                current: current.as_ref() == Some(&version),
+                default: default.as_ref() == Some(&version),
+                broken,
+                version,
+            });
+        }
+    }
+    versions.sort_by(|a, b| a.version.cmp(&b.version));
+
+    let system = match filter {
+        Some(_) => {
+            aliases.clear();
+            None
+        },
+        None => system_node_version().await,
+    };
+
+    Ok(Listing {
+        versions,
+        aliases,
+        system_current: current.is_none() && system.is_some(),
+        system,
+    })
+}
+
+impl Listing {
+    /// The same layout as `nvm ls`, the current version is marked
+    /// with `->` and the default version with `*`
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![];
+
+        for installed in &self.versions {
+            let mut line = format!(
+                "{:>2} {:>15}{}",
+                if installed.current { "->" } else { "" },
+                VersionSpec { runtime: Some(installed.runtime), version: installed.version.clone() }.to_string(),
+                if installed.default { " *" } else { "" },
+            );
+
+            let mut tags = installed.aliases.clone();
+            if installed.broken {
+                tags.insert(0, "broken".to_string());
+            }
+            if let Some(ref codename) = installed.lts {
+                let lts_alias = format!("lts/{}", codename.to_lowercase());
+                if !tags.contains(&lts_alias) {
+                    tags.push(lts_alias);
+                }
+            }
+            if !tags.is_empty() {
+                line.push_str(&format!(" ({})", tags.join(", ")));
+            }
+            lines.push(line);
+        }
+
+        if self.system.is_some() {
+            lines.push(format!("{:>2} {:>15}", if self.system_current { "->" } else { "" }, "system"));
+        }
+
+        if self.versions.is_empty() && self.system.is_none() {
+            lines.push(format!("{:>18}", "N/A"));
+        }
+
+        for alias in &self.aliases {
+            let mut line = format!("{} -> {}", alias.name, alias.target);
+            match alias.version {
+                Some(ref version) if version.to_string() == alias.target => {},
+                Some(ref version) => line.push_str(&format!(" (-> {})", version)),
+                None => line.push_str(" (-> N/A)"),
+            }
+            lines.push(line);
+        }
+
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let versions = self.versions
+            .iter()
+            .map(|installed| json!({
+                "version": installed.version.to_string(),
+                "runtime": installed.runtime.name(),
+                "location": installed.version.location,
+                "aliases": installed.aliases,
+                "lts": installed.lts,
+                "current": installed.current,
+                "default": installed.default,
+                "broken": installed.broken,
+            }))
+            .collect::<Vec<_>>();
+        let aliases = self.aliases
+            .iter()
+            .map(|alias| json!({
+                "name": alias.name,
+                "target": alias.target,
+                "chain": alias.chain,
+                "version": alias.version.as_ref().map(|version| version.to_string()),
+            }))
+            .collect::<Vec<_>>();
+
+        json!({
+            "versions": versions,
+            "aliases": aliases,
+            "system": self.system.as_ref().map(|version| json!({
+                "version": version.to_string(),
+                "current": self.system_current,
+            })),
+        }).to_string()
+    }
+}
@@ -5,209 +5,422 @@ mod nvmrc;
 mod version;
 mod query_current;
 mod install_node;
+mod mirror;
+mod verify;
+mod index;
+mod version_req;
+mod version_file;
+mod cli;
+mod init;
+mod alias;
+mod list_versions;
+mod list_remote;
+mod manage_installs;
+mod exec;
 
-use std::env::current_dir;
+use std::env::{args, current_dir};
+use std::path::PathBuf;
+use std::process::exit;
 use install_node::install_node;
 use query_current::{npm_version, system_node_version};
-use tokio::fs;
-use manage_changeset::{set_node_version, revert_to_system_version};
-use env_utils::gen_shell_script;
-use version::{NodeVersion, find_version};
+use manage_changeset::{set_node_version, revert_to_system_version, is_pinned, pin_version, unpin_version, Switch};
+use env_utils::{gen_shell_script, Shell};
+use version::{NodeVersion, VersionSpec, find_version};
 use misc::get_runtime_name;
+use cli::{Command, Format};
 
 #[tokio::main]
 async fn main() {
-    let cwd = current_dir().expect("couldn't get CWD");
-    let nvmrc = nvmrc::find_nvmrc(cwd).await;
-    let current_node_version = version::find_current_version(misc::PATH.as_str());
+    let args = match cli::parse_args(args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("quicknvm: {}", err);
+            exit(1);
+        },
+    };
 
-    // TODO: Refactor this into a `match tuple ...` somehow... Or just find some other
-    // way to deduplicate it...
-    let changesets_and_version = if let Some(nvmrc_path) = nvmrc {
-        let nvmrc_contents = fs::read_to_string(&nvmrc_path).await.expect("couldn't read nvmrc file");
-        let nvmrc_version = nvmrc::resolve_nvmrc_version(
-            nvmrc_contents.as_str(),
-            0
-        ).await;
-
-        let nvmrc_version = if let Ok(version) = nvmrc_version {
-            version
-        } else {
-            eprintln!("Invalid .nvmrc '{}'", nvmrc_path.to_str().unwrap());
-            return;
-        };
-
-        match nvmrc_version {
-            NodeVersion::NvmVersion(version) => {
-                let has_same_node_version = current_node_version
-                    .map_or(false, |current| {
-                        version
-                            .as_ref()
-                            .map(|version| version.matches(&current))
-                            .unwrap_or(false)
-                    });
-
-                if has_same_node_version {
-                    None
-                } else {
-                    let nvmrc_contents = nvmrc_contents.trim();
-                    eprintln!(
-                        "Found '{}' with version <{}>",
-                        nvmrc_path.to_str().unwrap(),
-                        nvmrc_contents
-                    );
-                    let version = match version {
-                        Some(version) => version,
-                        None => {
-                            // We got an implicit version like `node`/`iojs`,
-                            // install them first...
-                            match install_node(nvmrc_contents).await {
-                                Ok(version) => version,
-                                _ => {
-                                    eprintln!("Failed to install {}", nvmrc_contents.trim());
-                                    return;
-                                }
-                            }
-                        },
-                    };
+    match args.command {
+        Command::Init(shell) => println!("{}", init::gen_init_script(shell)),
+        Command::Ls(filter) => {
+            let listing = match list_versions::list_versions(filter.as_ref()).await {
+                Ok(listing) => listing,
+                Err(err) => {
+                    eprintln!("quicknvm: couldn't list versions: {:#}", err);
+                    exit(1);
+                },
+            };
+
+            match args.format {
+                Format::Shell => println!("{}", listing.to_text()),
+                Format::Json => println!("{}", listing.to_json()),
+            }
+        },
+        Command::LsRemote(query) => {
+            let listing = match list_remote::list_remote(&query).await {
+                Ok(listing) => listing,
+                Err(err) => {
+                    eprintln!("quicknvm: couldn't list releases: {:#}", err);
+                    exit(1);
+                },
+            };
 
-                    let installed_version = match find_version(&version).await {
-                        Err(_) => {
-                            // Node version isn't installed... Try installing it
-                            // TODO: add some way to check if a version exists before installing it
-                            let new_installed_version = install_node(nvmrc_contents).await;
-                            if let Ok(version) = new_installed_version {
-                                version
-                            } else {
-                                // Failed to install version
-                                return;
-                            }
+            match args.format {
+                Format::Shell => println!("{}", listing.to_text()),
+                Format::Json => println!("{}", listing.to_json()),
+            }
+        },
+        Command::Install(spec, options) => {
+            let spec = match spec {
+                Some(spec) => spec,
+                // Like nvm, install what the current directory wants
+                None => {
+                    let cwd = current_dir().expect("couldn't get CWD");
+                    match version_file::find_version_file(cwd).await {
+                        Some(version_file) => version_file.version,
+                        None => {
+                            eprintln!("quicknvm: no version given and no version file found");
+                            exit(1);
                         },
-                        Ok(version) => version
-                    };
+                    }
+                },
+            };
 
-                    let npm_version = npm_version(Some(&installed_version))
-                        .await
-                        .map(|version| format!(" (npm {})", version))
-                        .unwrap_or_else(|_| String::new());
-                    eprintln!("Now using {} {}{}", get_runtime_name(&installed_version), installed_version, npm_version);
-                    Some(set_node_version(&installed_version).await)
+            match manage_installs::install(&spec, &options).await {
+                Ok(installation) => {
+                    if let Format::Json = args.format {
+                        println!("{}", installation.to_json());
+                    }
+                },
+                Err(err) => {
+                    eprintln!("quicknvm: couldn't install {}: {:#}", spec, err);
+                    exit(1);
+                },
+            }
+        },
+        Command::Uninstall { spec, force } => match manage_installs::uninstall(&spec, force).await {
+            Ok(uninstallation) => {
+                eprintln!("Uninstalled {}", uninstallation.version);
+                for alias in &uninstallation.removed_aliases {
+                    eprintln!("Deleted alias {}", alias);
                 }
+                if let Format::Json = args.format {
+                    println!("{}", uninstallation.to_json());
+                }
+            },
+            Err(err) => {
+                eprintln!("quicknvm: couldn't uninstall {}: {:#}", spec, err);
+                exit(1);
             },
+        },
+        Command::Aliases => {
+            let listing = match alias::describe_aliases().await {
+                Ok(listing) => listing,
+                Err(err) => {
+                    eprintln!("quicknvm: couldn't list aliases: {:#}", err);
+                    exit(1);
+                },
+            };
 
-            NodeVersion::System => {
-                if let Some(_) = current_node_version {
-                    // We need to switch to the system version of Node
-                    let system_version = system_node_version().await;
-                    eprintln!(
-                        "Found '{}' with version <{}>",
-                        nvmrc_path.to_str().unwrap(),
-                        nvmrc_contents.trim()
-                    );
-                    let version_message = if let Some(ref version) = system_version {
-                        let npm_version = npm_version(None)
-                            .await
-                            .map(|version| format!(" (npm {})", version))
-                            .unwrap_or_else(|_| String::new());
-                        format!("Now using system version of Node: {}{}", version, npm_version)
-                    } else {
-                        "System version of node not found.".to_string()
-                    };
+            match args.format {
+                Format::Shell if listing.aliases.is_empty() => {},
+                Format::Shell => println!("{}", listing.to_text()),
+                Format::Json => println!("{}", listing.to_json()),
+            }
+        },
+        Command::ShowAlias(name) => {
+            let info = match alias::describe_alias(&name).await {
+                Ok(info) => info,
+                Err(err) => {
+                    eprintln!("quicknvm: couldn't show alias {}: {:#}", name, err);
+                    exit(1);
+                },
+            };
+
+            match args.format {
+                Format::Shell => println!("{}", info.to_text()),
+                Format::Json => println!("{}", info.to_json()),
+            }
+        },
+        Command::SetAlias { name, target } => {
+            if let Err(err) = alias::set_alias(&name, &target).await {
+                eprintln!("quicknvm: couldn't set alias {}: {:#}", name, err);
+                exit(1);
+            }
+
+            let info = alias::AliasInfo::new(&name).await;
+            match args.format {
+                Format::Shell => println!("{}", info.to_text()),
+                Format::Json => println!("{}", info.to_json()),
+            }
+        },
+        Command::Unalias(name) => match alias::remove_alias(&name).await {
+            Ok(()) => eprintln!("Deleted alias {}", name),
+            Err(err) => {
+                eprintln!("quicknvm: couldn't delete alias {}: {:#}", name, err);
+                exit(1);
+            },
+        },
+        Command::Exec { spec, command } => {
+            // Without a version this behaves like the hooks, except
+            // that a version pinned with `quicknvm use` doesn't count
+            let switch = match spec {
+                Some(ref spec) => switch_to_spec(spec).await,
+                None => auto_switch().await,
+            };
+            let switch = match switch {
+                Some(switch) => switch,
+                None => {
+                    eprintln!("quicknvm: couldn't find a version to run {} with", command[0]);
+                    exit(1);
+                },
+            };
+
+            let err = exec::exec_with_changes(&command, &switch.changesets);
+            eprintln!("quicknvm: {:#}", err);
+            exit(1);
+        },
+        Command::Shell(spec) => {
+            let switch = match spec {
+                Some(ref spec) => switch_to_spec(spec).await,
+                None => auto_switch().await,
+            };
+            let mut switch = match switch {
+                Some(switch) => switch,
+                None => {
+                    eprintln!("quicknvm: couldn't find a version for the shell");
+                    exit(1);
+                },
+            };
 
-                    eprintln!("{}", version_message);
-                    Some(revert_to_system_version())
-                } else {
-                    // Already using the system version of Node
-                    None
+            // Pinning keeps the hooks in the subshell from switching away
+            let pinned_spec = match (spec, switch.version.as_ref()) {
+                (Some(spec), _) => spec,
+                (None, Some(version)) => version.to_string(),
+                (None, None) => "system".to_string(),
+            };
+            switch.changesets.push(pin_version(&pinned_spec));
+            eprintln!("Starting a shell with {}, exit it to go back", pinned_spec);
+
+            let err = exec::exec_shell(&switch.changesets);
+            eprintln!("quicknvm: {:#}", err);
+            exit(1);
+        },
+        Command::Auto => {
+            // `quicknvm use` pins a version until it's run without one
+            if is_pinned() {
+                if let Format::Json = args.format {
+                    println!("null");
                 }
+                return;
+            }
+
+            print_switch(auto_switch().await, args.format, args.shell).await;
+        },
+        Command::Use(Some(spec)) => match use_version(&spec).await {
+            Some(switch) => print_switch(Some(switch), args.format, args.shell).await,
+            None => exit(1),
+        },
+        Command::Use(None) => {
+            // Go back to switching automatically
+            let mut switch = auto_switch().await.unwrap_or(Switch {
+                nvmrc: None,
+                version: None,
+                changesets: vec![],
+            });
+            if is_pinned() {
+                switch.changesets.push(unpin_version());
+            }
+            print_switch(Some(switch), args.format, args.shell).await;
+        },
+    }
+}
+
+async fn print_switch(switch: Option<Switch>, format: Format, shell: Shell) {
+    match (switch, format) {
+        (Some(switch), Format::Shell) => {
+            if !switch.changesets.is_empty() {
+                println!("{}", gen_shell_script(&switch.changesets, shell));
+            }
+        },
+        (Some(switch), Format::Json) => println!("{}", switch.to_json().await),
+        (None, Format::Json) => println!("null"),
+        (None, Format::Shell) => {},
+    }
+}
+
+/// Figures out which version the current directory wants and
+/// what needs to change in the environment to switch to it.
+/// Returns None if there's nothing sensible to switch to
+async fn auto_switch() -> Option<Switch> {
+    let cwd = current_dir().expect("couldn't get CWD");
+    let version_file = version_file::find_version_file(cwd).await;
+
+    let mut alias_chain = vec![];
+    let (spec, resolved_version) = if let Some(ref version_file) = version_file {
+        match version_file.resolve(&mut alias_chain).await {
+            Ok(version) => (version_file.version.clone(), version),
+            Err(err) => {
+                eprintln!("Invalid {} '{}': {:#}", version_file.file_name(), version_file.path.to_str().unwrap(), err);
+                return None;
             },
         }
     } else {
-        let default_version = nvmrc::resolve_nvmrc_version("default", 0).await;
         // If we can't resolve what the default version is
         // then just give up
-        if default_version.is_err() {
-            // TODO: add verbosity option that will explain why
-            // this has happened
-            return;
-        }
-        let default_version = default_version.unwrap();
-
-        match default_version {
-            NodeVersion::NvmVersion(version) => {
-                let has_same_node_version = current_node_version
-                    .map_or(false, |current| {
-                        version
-                            .as_ref()
-                            .map(|version| version.matches(&current))
-                            .unwrap_or(false)
-                    });
-
-                if has_same_node_version {
-                    None
-                } else {
-                    eprintln!("Reverting to nvm default version");
-                    let version = match version {
-                        Some(version) => version,
-                        None => {
-                            // We got an implicit version like `node`/`iojs`,
-                            // install it first...
-                            match install_node("default").await {
-                                Ok(version) => version,
-                                _ => {
-                                    eprintln!("Failed to install default");
-                                    return;
-                                }
-                            }
-                        },
-                    };
-                    let installed_version = match find_version(&version).await {
-                        Err(_) => {
-                            // Node version isn't installed... Try installing it
-                            // TODO: add some way to check if a version exists before installing it
-                            let new_installed_version = install_node(version.to_string().as_str()).await;
-                            if let Ok(version) = new_installed_version {
-                                version
-                            } else {
-                                // Failed to install version
-                                return;
-                            }
-                        },
-                        Ok(version) => version
-                    };
+        // TODO: add verbosity option that will explain why
+        // this has happened
+        let default_version = nvmrc::resolve_nvmrc_version("default", 0).await.ok()?;
+        ("default".to_string(), default_version)
+    };
 
-                    let npm_version = npm_version(Some(&installed_version))
-                        .await
-                        .map(|version| format!(" (npm {})", version))
-                        .unwrap_or_else(|_| String::new());
-                    eprintln!("Now using {} {}{}", get_runtime_name(&installed_version), installed_version, npm_version);
-                    Some(set_node_version(&installed_version).await)
-                }
-            },
-            NodeVersion::System => {
-                if let Some(_) = current_node_version {
-                    // We need to switch to the system version of Node
-                    let system_version = system_node_version().await;
-                    let version_message = if let Some(ref version) = system_version {
-                        format!("Now using system version of Node: {}", version)
-                    } else {
-                        "System version of node not found.".to_string()
-                    };
+    // Installs use whatever the aliases ended up pointing to
+    let install_spec = alias_chain.last().unwrap_or(&spec).clone();
+    let nvmrc = version_file.as_ref().map(|version_file| version_file.path.clone());
+    let announce_switch = || match version_file {
+        Some(ref version_file) => eprintln!(
+            "Found '{}' with version <{}>",
+            version_file.path.to_str().unwrap(),
+            if alias_chain.len() > 1 { alias_chain.join(" -> ") } else { spec.clone() }
+        ),
+        None => eprintln!("Reverting to nvm default version"),
+    };
 
-                    eprintln!("Reverting to nvm default version");
-                    eprintln!("{}", version_message);
+    switch_to(resolved_version, &install_spec, nvmrc, announce_switch).await
+}
 
-                    Some(revert_to_system_version())
-                } else {
-                    // Already using the system version of Node
-                    None
-                }
-            },
-        }
+/// Switches to a version by hand and pins it so that
+/// the hooks don't switch away from it
+async fn use_version(spec: &str) -> Option<Switch> {
+    let mut switch = switch_to_spec(spec).await?;
+    switch.changesets.push(pin_version(spec));
+    Some(switch)
+}
+
+/// Works out the changes to switch to a version given by hand
+async fn switch_to_spec(spec: &str) -> Option<Switch> {
+    let mut alias_chain = vec![];
+    let resolved_version = match nvmrc::resolve_nvmrc_version_chain(spec, 0, &mut alias_chain).await {
+        Ok(version) => version,
+        Err(err) => {
+            eprintln!("Invalid version '{}': {:#}", spec, err);
+            return None;
+        },
     };
 
-    if let Some(changesets) = changesets_and_version {
-        let shell_script = gen_shell_script(&changesets);
-        println!("{}", &shell_script[1..]);
+    let install_spec = alias_chain.last().map(String::as_str).unwrap_or(spec);
+    switch_to(resolved_version, install_spec, None, || {}).await
+}
+
+/// Installs the resolved version if it's missing and works out the
+/// changes to switch to it. `install_spec` is what gets installed
+/// when no version could be resolved locally, and `announce_switch`
+/// is called before actually switching
+async fn switch_to<F: Fn()>(
+    resolved_version: NodeVersion,
+    install_spec: &str,
+    nvmrc: Option<PathBuf>,
+    announce_switch: F,
+) -> Option<Switch> {
+    let current_node_version = version::find_current_version(misc::PATH.as_str());
+
+    match resolved_version {
+        NodeVersion::NvmVersion(version) => {
+            let has_same_node_version = current_node_version
+                .as_ref()
+                .is_some_and(|current| {
+                    version
+                        .as_ref()
+                        .map(|version| version.matches(current))
+                        .unwrap_or(false)
+                });
+
+            if has_same_node_version {
+                return Some(Switch {
+                    nvmrc,
+                    version: current_node_version,
+                    changesets: vec![],
+                });
+            }
+
+            announce_switch();
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    // We got an implicit version like `node`/`iojs`,
+                    // install them first...
+                    match install_node(install_spec).await {
+                        Ok(version) => version,
+                        Err(err) => {
+                            eprintln!("Failed to install {}: {:#}", install_spec, err);
+                            return None;
+                        }
+                    }
+                },
+            };
+
+            let installed_version = match find_version(&version).await {
+                Err(_) => {
+                    // Node version isn't installed... Try installing the
+                    // newest release that matches it, the spec might
+                    // say which runtime it's from like `iojs-v3.3.1`
+                    let exact_install_spec = match install_spec.parse::<VersionSpec>() {
+                        Ok(spec) if spec.version.matches(&version) => install_spec.to_string(),
+                        _ => version.to_string(),
+                    };
+                    match install_node(&exact_install_spec).await {
+                        Ok(version) => version,
+                        Err(err) => {
+                            eprintln!("Failed to install {}: {:#}", version, err);
+                            return None;
+                        }
+                    }
+                },
+                Ok(version) => version
+            };
+
+            let npm_version = npm_version(Some(&installed_version))
+                .await
+                .map(|version| format!(" (npm {})", version))
+                .unwrap_or_else(|_| String::new());
+            eprintln!("Now using {} {}{}", get_runtime_name(&installed_version), installed_version, npm_version);
+            let changesets = set_node_version(&installed_version).await;
+
+            Some(Switch {
+                nvmrc,
+                version: Some(installed_version),
+                changesets,
+            })
+        },
+
+        NodeVersion::System => {
+            if current_node_version.is_none() {
+                // Already using the system version of Node
+                return Some(Switch {
+                    nvmrc,
+                    version: None,
+                    changesets: vec![],
+                });
+            }
+
+            // We need to switch to the system version of Node
+            announce_switch();
+            let system_version = system_node_version().await;
+            let version_message = if let Some(ref version) = system_version {
+                let npm_version = npm_version(None)
+                    .await
+                    .map(|version| format!(" (npm {})", version))
+                    .unwrap_or_else(|_| String::new());
+                format!("Now using system version of Node: {}{}", version, npm_version)
+            } else {
+                "System version of node not found.".to_string()
+            };
+
+            eprintln!("{}", version_message);
+            Some(Switch {
+                nvmrc,
+                version: None,
+                changesets: revert_to_system_version(),
+            })
+        },
     }
 }
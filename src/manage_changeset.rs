@@ -1,13 +1,70 @@
+use std::env::var as get_env_var;
+use std::path::PathBuf;
+use serde_json::json;
 use crate::version::Version;
 use crate::env_utils::{EnvChangeset, get_new_env, strip_nvm_path};
-use crate::misc::PATH;
+use crate::misc::{PATH, get_runtime_name};
+use crate::query_current::{npm_version, system_node_version};
+
+/// What quicknvm decided to switch to, and how
+pub struct Switch {
+    /// The `.nvmrc` that picked the version, None when
+    /// falling back to the nvm default version
+    pub nvmrc: Option<PathBuf>,
+    /// The nvm-managed version, None means the system version
+    pub version: Option<Version>,
+    /// Empty when the shell is already using the right version
+    pub changesets: Vec<EnvChangeset>,
+}
+
+impl Switch {
+    /// A machine-readable description of the switch for tools
+    /// that don't want to parse shell code
+    pub async fn to_json(&self) -> String {
+        let system = self.version.is_none();
+        let version = match self.version {
+            Some(ref version) => Some(version.clone()),
+            None => system_node_version().await,
+        };
+        let npm_version = npm_version(self.version.as_ref()).await.ok();
+
+        json!({
+            "changes": self.changesets,
+            "system": system,
+            "version": version.as_ref().map(|version| version.to_string()),
+            "location": self.version.as_ref().and_then(|version| version.location.as_ref()),
+            "runtime": version.as_ref().map(get_runtime_name),
+            "nvmrc": self.nvmrc,
+            "npm_version": npm_version.map(|version| version.to_string()),
+        }).to_string()
+    }
+}
+
+/// Set by `quicknvm use` so that the hooks leave the version
+/// that was picked by hand alone in that shell
+pub const PINNED_VAR: &str = "QUICKNVM_PINNED";
+
+pub fn is_pinned() -> bool {
+    get_env_var(PINNED_VAR).is_ok_and(|spec| !spec.is_empty())
+}
+
+pub fn pin_version(spec: &str) -> EnvChangeset {
+    EnvChangeset::UpdateVar {
+        name: PINNED_VAR.to_string(),
+        value: spec.to_string(),
+    }
+}
+
+pub fn unpin_version() -> EnvChangeset {
+    EnvChangeset::DeleteVar { name: PINNED_VAR.to_string() }
+}
 
 pub async fn set_node_version(version: &Version) -> Vec<EnvChangeset> {
     let location = version.location.as_ref().unwrap().to_str().unwrap();
     let changesets = vec![
         EnvChangeset::UpdateVar {
             name: "PATH".to_string(),
-            value: get_new_env(&version, strip_nvm_path(PATH.as_str()).as_str(), "/bin"),
+            value: get_new_env(version, strip_nvm_path(PATH.as_str()).as_str(), "/bin"),
         },
         EnvChangeset::UpdateVar {
             name: "NVM_BIN".to_string(),
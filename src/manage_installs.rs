@@ -0,0 +1,230 @@
+use std::process::Stdio;
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use tokio::fs;
+use tokio::process::Command;
+use crate::alias::{alias_chain, alias_status, list_aliases, AliasStatus};
+use crate::env_utils::strip_nvm_path;
+use crate::install_node::{install_node, remove_broken_install, resolve_release};
+use crate::misc::{nvm_path, Runtime, NVM_ALIAS_DIR, PATH};
+use crate::nvmrc::{resolve_nvmrc_version, LTS_STR_START};
+use crate::version::{BrokenInstall, NodeVersion, Version, VersionSpec, find_current_version, find_version, find_version_of};
+
+/// Packages that come with Node.js and are never reinstalled
+const BUNDLED_PACKAGES: [&str; 2] = ["npm", "corepack"];
+
+pub struct InstallOptions {
+    /// Upgrade npm to its latest version after installing
+    pub latest_npm: bool,
+    /// Install the global packages of another installed version
+    pub reinstall_packages_from: Option<String>,
+}
+
+/// What `quicknvm install` did
+pub struct Installation {
+    pub version: Version,
+    pub runtime: Runtime,
+    /// False if the version was installed already
+    pub newly_installed: bool,
+    /// Global packages installed from `--reinstall-packages-from`
+    pub reinstalled_packages: Vec<String>,
+}
+
+/// What `quicknvm uninstall` did
+pub struct Uninstallation {
+    pub version: Version,
+    /// Aliases removed because they pointed to the version
+    pub removed_aliases: Vec<String>,
+}
+
+/// Installs the newest release matching `spec` (which can be an
+/// alias) like `nvm install`, then applies the `options`
+pub async fn install(spec: &str, options: &InstallOptions) -> Result<Installation> {
+    // Install whatever an alias ends up pointing to, LTS aliases are
+    // skipped since nvm's copies might be older than the mirror's
+    let chain = if spec.starts_with(LTS_STR_START) {
+        vec![spec.to_string()]
+    } else {
+        alias_chain(spec).await
+    };
+    let spec = chain.last().unwrap();
+    if spec == "system" {
+        return Err(anyhow!("the system version can't be installed"));
+    }
+
+    // Make sure the version to copy packages from exists before installing anything
+    let packages_source = match options.reinstall_packages_from {
+        Some(ref source_spec) => Some(find_installed(source_spec).await?),
+        None => None,
+    };
+
+    let (release_version, runtime) = resolve_release(spec).await?;
+    let (version, newly_installed) = match find_version_of(&release_version, runtime.into()).await {
+        Ok(version) => {
+            eprintln!("{} {} is already installed", runtime.name(), version);
+            (version, false)
+        },
+        Err(err) => {
+            // Asking for the version explicitly is what repairs it
+            if let Some(broken_install) = err.downcast_ref::<BrokenInstall>() {
+                eprintln!("{}, reinstalling it", broken_install);
+                remove_broken_install(broken_install).await?;
+            }
+
+            eprintln!("Downloading and installing {} {}...", runtime.name(), release_version);
+            let exact_spec = VersionSpec { runtime: Some(runtime), version: release_version };
+            (install_node(&exact_spec.to_string()).await?, true)
+        },
+    };
+
+    if options.latest_npm {
+        eprintln!("Installing the latest npm...");
+        run_npm(&version, &["install", "-g", "npm@latest"]).await?;
+    }
+
+    let mut reinstalled_packages = vec![];
+    if let Some(source) = packages_source {
+        reinstalled_packages = global_packages(&source).await?;
+        if !reinstalled_packages.is_empty() {
+            eprintln!("Reinstalling global packages from {}: {}", source, reinstalled_packages.join(" "));
+            let mut npm_args = vec!["install", "-g"];
+            npm_args.extend(reinstalled_packages.iter().map(String::as_str));
+            run_npm(&version, &npm_args).await?;
+        }
+    }
+
+    Ok(Installation {
+        version,
+        runtime,
+        newly_installed,
+        reinstalled_packages,
+    })
+}
+
+/// Removes an installed version like `nvm uninstall`. The current and
+/// default versions are only removed when `force` is set
+pub async fn uninstall(spec: &str, force: bool) -> Result<Uninstallation> {
+    // Broken installs are removed the same way
+    let version = match find_installed(spec).await {
+        Ok(version) => version,
+        Err(err) => err.downcast::<BrokenInstall>()?.version,
+    };
+
+    if !force {
+        if find_current_version(PATH.as_str()).as_ref() == Some(&version) {
+            return Err(anyhow!("{} is the current version, pass --force to uninstall it anyway", version));
+        }
+        // The default alias can point to a broken install too
+        let default_version = match alias_status(&alias_chain("default").await).await {
+            AliasStatus::Installed(version) | AliasStatus::NotInstalled(Some(version)) => Some(version),
+            _ => None,
+        };
+        if default_version.as_ref() == Some(&version) {
+            return Err(anyhow!("{} is the default version, pass --force to uninstall it anyway", version));
+        }
+    }
+
+    let location = version.location.as_ref().unwrap();
+    fs::remove_dir_all(location)
+        .await
+        .with_context(|| format!("couldn't remove {}", location.display()))?;
+
+    // Like nvm, aliases that point straight to the version go with it
+    let mut removed_aliases = vec![];
+    for alias in list_aliases().await? {
+        let is_top_level = !alias.name.contains('/');
+        let points_to_version = alias
+            .target
+            .parse::<VersionSpec>()
+            .is_ok_and(|target| target.version.is_full() && target.version == version);
+        if is_top_level && points_to_version {
+            fs::remove_file(format!("{}/{}", nvm_path(NVM_ALIAS_DIR), alias.name)).await?;
+            removed_aliases.push(alias.name);
+        }
+    }
+
+    Ok(Uninstallation {
+        version,
+        removed_aliases,
+    })
+}
+
+/// Resolves a version string to one that's installed
+async fn find_installed(spec: &str) -> Result<Version> {
+    match resolve_nvmrc_version(spec, 0).await? {
+        NodeVersion::NvmVersion(Some(version)) => find_version(&version).await,
+        NodeVersion::NvmVersion(None) => Err(anyhow!("no installed version matches '{}'", spec)),
+        NodeVersion::System => Err(anyhow!("the system version isn't managed by nvm")),
+    }
+}
+
+/// The globally installed packages of a version, read straight
+/// from its `lib/node_modules` instead of asking npm
+async fn global_packages(version: &Version) -> Result<Vec<String>> {
+    let modules_dir = version.location.as_ref().unwrap().join("lib/node_modules");
+    let mut packages = vec![];
+
+    let mut entries = match fs::read_dir(&modules_dir).await {
+        Ok(entries) => entries,
+        Err(_) => return Ok(packages),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || BUNDLED_PACKAGES.contains(&name.as_str()) {
+            continue;
+        }
+
+        // Scoped packages are one level deeper, e.g. `@scope/name`
+        if name.starts_with('@') {
+            let mut scoped_entries = fs::read_dir(entry.path()).await?;
+            while let Some(scoped_entry) = scoped_entries.next_entry().await? {
+                packages.push(format!("{}/{}", name, scoped_entry.file_name().to_string_lossy()));
+            }
+        } else {
+            packages.push(name);
+        }
+    }
+
+    packages.sort();
+    Ok(packages)
+}
+
+/// Runs the npm of an installed version with its own node first in
+/// the PATH. npm's output goes to stderr to keep stdout clean
+async fn run_npm(version: &Version, args: &[&str]) -> Result<()> {
+    let bin_dir = version.location.as_ref().unwrap().join("bin");
+    let status = Command::new(bin_dir.join("npm"))
+        .args(args)
+        .env("PATH", format!("{}:{}", bin_dir.display(), strip_nvm_path(PATH.as_str())))
+        .stdout(Stdio::from(std::io::stderr()))
+        .status()
+        .await
+        .with_context(|| format!("couldn't run npm from {}", version))?;
+
+    if !status.success() {
+        return Err(anyhow!("npm {} failed ({})", args.join(" "), status));
+    }
+    Ok(())
+}
+
+impl Installation {
+    pub fn to_json(&self) -> String {
+        json!({
+            "version": self.version.to_string(),
+            "runtime": self.runtime.name(),
+            "location": self.version.location,
+            "installed": self.newly_installed,
+            "reinstalled_packages": self.reinstalled_packages,
+        }).to_string()
+    }
+}
+
+impl Uninstallation {
+    pub fn to_json(&self) -> String {
+        json!({
+            "version": self.version.to_string(),
+            "location": self.version.location,
+            "removed_aliases": self.removed_aliases,
+        }).to_string()
+    }
+}
@@ -0,0 +1,168 @@
+use std::env::var as get_env_var;
+use std::future::Future;
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use lazy_static::lazy_static;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use crate::misc::Runtime;
+use crate::version::Version;
+
+pub const NODEJS_ORG_MIRROR: &str = "https://nodejs.org/dist";
+pub const IOJS_ORG_MIRROR: &str = "https://iojs.org/dist";
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .user_agent(concat!("quicknvm/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("couldn't create HTTP client");
+}
+
+/// Which kind of releases a mirror has, release candidates and
+/// nightly builds are distributed separately from normal releases
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Release,
+    Rc,
+    Nightly,
+}
+
+impl Channel {
+    /// Pre-releases have the channel in their name, e.g.
+    /// `v21.0.0-rc.2` or `v22.0.0-nightly20240101abcdef`
+    pub fn of(version: &Version) -> Self {
+        match version.pre.as_deref() {
+            Some(pre) if pre.starts_with("rc") => Channel::Rc,
+            Some(pre) if pre.starts_with("nightly") => Channel::Nightly,
+            _ => Channel::Release,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Release => "release",
+            Channel::Rc => "rc",
+            Channel::Nightly => "nightly",
+        }
+    }
+}
+
+/// The base URLs releases of `runtime` are downloaded from, in the
+/// order they should be tried. The main mirror can be changed with
+/// `NVM_NODEJS_ORG_MIRROR`/`NVM_IOJS_ORG_MIRROR` just like with nvm,
+/// and `QUICKNVM_NODEJS_MIRROR_FALLBACKS`/`QUICKNVM_IOJS_MIRROR_FALLBACKS`
+/// add comma or space separated mirrors to try if it fails.
+///
+/// Release candidates and nightly builds only come from the mirrors
+/// in `QUICKNVM_RC_MIRROR`/`QUICKNVM_NIGHTLY_MIRROR`, there's none
+/// by default
+pub fn mirror_urls(runtime: Runtime, channel: Channel) -> Vec<String> {
+    let (mirror_var, fallbacks_var, default_mirror) = match (channel, runtime) {
+        (Channel::Release, Runtime::Node) => ("NVM_NODEJS_ORG_MIRROR", Some("QUICKNVM_NODEJS_MIRROR_FALLBACKS"), NODEJS_ORG_MIRROR),
+        (Channel::Release, Runtime::Iojs) => ("NVM_IOJS_ORG_MIRROR", Some("QUICKNVM_IOJS_MIRROR_FALLBACKS"), IOJS_ORG_MIRROR),
+        (Channel::Rc, _) => ("QUICKNVM_RC_MIRROR", None, ""),
+        (Channel::Nightly, _) => ("QUICKNVM_NIGHTLY_MIRROR", None, ""),
+    };
+
+    let mirror = get_env_var(mirror_var)
+        .ok()
+        .filter(|mirror| !mirror.is_empty())
+        .unwrap_or_else(|| default_mirror.to_string());
+    let fallbacks = fallbacks_var
+        .and_then(|fallbacks_var| get_env_var(fallbacks_var).ok())
+        .unwrap_or_default();
+
+    let mut mirrors: Vec<String> = vec![];
+    let all_mirrors = [mirror.as_str()]
+        .into_iter()
+        .chain(fallbacks.split([',', ' ', '\n']))
+        .map(|mirror| mirror.trim().trim_end_matches('/'))
+        .filter(|mirror| !mirror.is_empty());
+    for mirror in all_mirrors {
+        if !mirrors.iter().any(|existing| existing == mirror) {
+            mirrors.push(mirror.to_string());
+        }
+    }
+
+    mirrors
+}
+
+/// Runs `action` with each of the mirrors for `runtime` until
+/// one of them succeeds, returning the last error otherwise
+pub async fn try_mirrors<T, F, Fut>(runtime: Runtime, channel: Channel, action: F) -> Result<T>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut last_error = None;
+
+    for mirror in mirror_urls(runtime, channel) {
+        match action(mirror).await {
+            Ok(value) => return Ok(value),
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow!("no {} mirror configured", channel.name())))
+}
+
+/// Local mirrors can be given as a plain path or a `file://` URL
+fn local_path(url: &str) -> Option<&str> {
+    if let Some(path) = url.strip_prefix("file://") {
+        Some(path)
+    } else if url.starts_with('/') {
+        Some(url)
+    } else {
+        None
+    }
+}
+
+/// Fetches a small text file like an index or checksum list
+pub async fn fetch_string(url: &str) -> Result<String> {
+    if let Some(path) = local_path(url) {
+        return fs::read_to_string(path)
+            .await
+            .with_context(|| format!("couldn't read {}", path));
+    }
+
+    let response = CLIENT
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("couldn't download {}", url))?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("couldn't download {} ({})", url, response.status()));
+    }
+
+    Ok(response.text().await?)
+}
+
+/// Downloads `url` into the file at `destination`, streaming it
+/// so that large tarballs don't have to fit in memory
+pub async fn download(url: &str, destination: &Path) -> Result<()> {
+    if let Some(path) = local_path(url) {
+        fs::copy(path, destination)
+            .await
+            .with_context(|| format!("couldn't copy {}", path))?;
+        return Ok(());
+    }
+
+    let mut response = CLIENT
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("couldn't download {}", url))?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("couldn't download {} ({})", url, response.status()));
+    }
+
+    let mut file = fs::File::create(destination).await?;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    Ok(())
+}
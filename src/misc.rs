@@ -1,4 +1,6 @@
 use std::env::var as get_env_var;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
 use anyhow::Result;
 use lazy_static::lazy_static;
 use tokio::fs;
@@ -7,10 +9,13 @@ use tokio_stream::StreamExt;
 use futures_util::future::join_all;
 use crate::version::Version;
 
-pub const NVM_VERSION_DIR_OLD: &str = ".nvm";
-pub const NVM_VERSION_DIR_NEW: &str = ".nvm/versions/node";
-pub const NVM_VERSION_DIR_OLD_IOJS: &str = ".nvm/io.js";
-pub const NVM_VERSION_DIR_NEW_IOJS: &str = ".nvm/versions/io.js";
+// These are all relative to `NVM_DIR`
+pub const NVM_VERSION_DIR_OLD: &str = "";
+pub const NVM_VERSION_DIR_NEW: &str = "versions/node";
+pub const NVM_VERSION_DIR_OLD_IOJS: &str = "io.js";
+pub const NVM_VERSION_DIR_NEW_IOJS: &str = "versions/io.js";
+pub const NVM_ALIAS_DIR: &str = "alias";
+pub const QUICKNVM_CACHE_DIR: &str = ".cache/quicknvm";
 
 lazy_static! {
     pub static ref HOME: String = get_env_var("HOME")
@@ -23,56 +28,147 @@ lazy_static! {
 }
 
 lazy_static! {
-    pub static ref DOT_NVM_HOME: String = format!("{}/.nvm/", HOME.as_str());
+    /// The root of the nvm install, taken from `NVM_DIR` like nvm
+    /// itself does and falling back to `$HOME/.nvm`. Never has a
+    /// trailing slash
+    pub static ref NVM_DIR: String = get_env_var("NVM_DIR")
+        .ok()
+        .map(|dir| dir.trim_end_matches('/').to_string())
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| format!("{}/.nvm", HOME.as_str()));
 }
 
-const IOJS_MIN: Version = Version { major: 1, minor: None, patch: None, location: None };
-const IOJS_MAX: Version = Version { major: 4, minor: None, patch: None, location: None };
+/// Joins a path relative to `NVM_DIR`, e.g. one of the
+/// `NVM_VERSION_DIR_*` constants
+pub fn nvm_path(relative_path: &str) -> String {
+    if relative_path.is_empty() {
+        NVM_DIR.to_string()
+    } else {
+        format!("{}/{}", NVM_DIR.as_str(), relative_path)
+    }
+}
+
+const IOJS_MIN: Version = Version { major: 1, minor: None, patch: None, pre: None, build: None, location: None };
+const IOJS_MAX: Version = Version { major: 4, minor: None, patch: None, pre: None, build: None, location: None };
 
 pub fn is_iojs(version: &Version) -> bool {
     version >= &IOJS_MIN && version < &IOJS_MAX
 }
 
 pub fn get_runtime_name(version: &Version) -> &str {
-    if is_iojs(version) { "io.js" } else { "node" }
+    Runtime::of(version).name()
+}
+
+/// Which project a version was released by, they're
+/// distributed from different mirrors with different names
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Runtime {
+    #[default]
+    Node,
+    Iojs,
+}
+
+impl Runtime {
+    pub fn of(version: &Version) -> Self {
+        if is_iojs(version) { Runtime::Iojs } else { Runtime::Node }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Runtime::Node => "node",
+            Runtime::Iojs => "io.js",
+        }
+    }
+
+    /// The prefix used for release artifact names
+    pub fn artifact_prefix(&self) -> &'static str {
+        match self {
+            Runtime::Node => "node",
+            Runtime::Iojs => "iojs",
+        }
+    }
+
+    /// Where nvm keeps versions of this runtime, relative to `NVM_DIR`
+    pub fn install_dir(&self) -> &'static str {
+        match self {
+            Runtime::Node => NVM_VERSION_DIR_NEW,
+            Runtime::Iojs => NVM_VERSION_DIR_NEW_IOJS,
+        }
+    }
+}
+
+/// Checks that an install has an executable `bin/node` like nvm
+/// does, an aborted install can leave behind an empty directory
+pub async fn has_node_binary(version_dir: &Path) -> bool {
+    fs::metadata(version_dir.join("bin/node"))
+        .await
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
 }
 
-// TODO: check if the Node variant is needed
-#[allow(dead_code)]
+/// Whether a broken install is just what an interrupted extract leaves
+/// behind (an empty directory or one without `bin/`), which is safe to
+/// remove without asking
+pub async fn is_failed_extract(version_dir: &Path) -> bool {
+    let is_empty = match fs::read_dir(version_dir).await {
+        Ok(mut entries) => entries.next_entry().await.is_ok_and(|entry| entry.is_none()),
+        Err(_) => false,
+    };
+    is_empty || !fs::metadata(version_dir.join("bin")).await.is_ok_and(|metadata| metadata.is_dir())
+}
+
+#[derive(Clone, Copy)]
 pub enum ListingType {
     Node,
     Iojs,
     Both,
 }
 
+impl ListingType {
+    /// The directories versions of this type can be installed
+    /// in, relative to `NVM_DIR`. The new layout comes first so
+    /// it wins when a version is in both
+    pub fn dirs(&self) -> Vec<&'static str> {
+        match self {
+            ListingType::Node => vec![NVM_VERSION_DIR_NEW, NVM_VERSION_DIR_OLD],
+            ListingType::Iojs => vec![NVM_VERSION_DIR_NEW_IOJS, NVM_VERSION_DIR_OLD_IOJS],
+            ListingType::Both => vec![
+                NVM_VERSION_DIR_NEW,
+                NVM_VERSION_DIR_OLD,
+                NVM_VERSION_DIR_NEW_IOJS,
+                NVM_VERSION_DIR_OLD_IOJS,
+            ],
+        }
+    }
+}
+
+impl From<Runtime> for ListingType {
+    fn from(runtime: Runtime) -> Self {
+        match runtime {
+            Runtime::Node => ListingType::Node,
+            Runtime::Iojs => ListingType::Iojs,
+        }
+    }
+}
+
 /// This function is gnarly... It could use some serious refactoring
 pub async fn list_all_nvm_versions(listing_type: ListingType) -> Result<Vec<Version>> {
-    let nvm_dirs: Vec<&'static str> = match listing_type {
-        ListingType::Node => vec![NVM_VERSION_DIR_OLD, NVM_VERSION_DIR_NEW],
-        ListingType::Iojs => vec![NVM_VERSION_DIR_NEW_IOJS, NVM_VERSION_DIR_OLD_IOJS],
-        ListingType::Both => vec![
-            NVM_VERSION_DIR_OLD,
-            NVM_VERSION_DIR_NEW,
-            NVM_VERSION_DIR_NEW_IOJS,
-            NVM_VERSION_DIR_OLD_IOJS,
-        ],
-    };
+    let nvm_dirs = listing_type.dirs();
     let read_dirs = join_all(
         nvm_dirs
             .into_iter()
             .map(|dir| async move {
-                fs::read_dir(format!("{}/{}", HOME.as_str(), dir)).await
+                fs::read_dir(nvm_path(dir)).await
             })
     ).await;
 
     let streams = read_dirs
         .into_iter()
         .filter_map(|listing| listing
-            .map(|read_dir| ReadDirStream::new(read_dir))
+            .map(ReadDirStream::new)
             .ok())
         .collect::<Vec<_>>();
 
-    if streams.len() == 0 {
+    if streams.is_empty() {
         return Ok(vec![])
     }
 
@@ -100,15 +196,20 @@ pub async fn list_all_nvm_versions(listing_type: ListingType) -> Result<Vec<Vers
 
     let mut versions = vec![];
     for entry in entries {
-        if entry.file_type().await?.is_dir() {
+        // Broken installs don't count as installed
+        if entry.file_type().await?.is_dir() && has_node_binary(&entry.path()).await {
             let parsed_version = entry
                 .file_name()
                 .to_str()
                 .unwrap()
                 .parse::<Version>();
             if let Ok(mut version) = parsed_version {
-                let _ = version.location.insert(entry.path());
-                versions.push(version);
+                // Only the first copy of a version is listed, which
+                // is the one in the preferred layout
+                if !versions.contains(&version) {
+                    let _ = version.location.insert(entry.path());
+                    versions.push(version);
+                }
             }
         }
     }
@@ -116,3 +217,36 @@ pub async fn list_all_nvm_versions(listing_type: ListingType) -> Result<Vec<Vers
     Ok(versions)
 }
 
+/// Lists the version directories without an executable `bin/node`,
+/// skipping any version that's installed properly in another layout
+pub async fn list_broken_nvm_versions(listing_type: ListingType) -> Result<Vec<Version>> {
+    let installed = list_all_nvm_versions(listing_type).await?;
+    let mut broken = vec![];
+
+    for dir in listing_type.dirs() {
+        let mut entries = match fs::read_dir(nvm_path(dir)).await {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let parsed_version = match file_name.to_str() {
+                Some(name) if name.starts_with('v') => name.parse::<Version>(),
+                _ => continue,
+            };
+            let mut version = match parsed_version {
+                Ok(version) => version,
+                Err(_) => continue,
+            };
+
+            let is_broken = entry.file_type().await?.is_dir() && !has_node_binary(&entry.path()).await;
+            if is_broken && !installed.contains(&version) && !broken.contains(&version) {
+                version.location = Some(entry.path());
+                broken.push(version);
+            }
+        }
+    }
+
+    Ok(broken)
+}
+
@@ -1,120 +1,98 @@
-use std::os::unix::prelude::MetadataExt;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use anyhow::{Context, Result, anyhow};
 use tokio_stream::wrappers::ReadDirStream;
 use tokio_stream::StreamExt;
 use async_recursion::async_recursion;
-use crate::misc::{list_all_nvm_versions, ListingType, is_iojs, NVM_VERSION_DIR_NEW_IOJS, NVM_VERSION_DIR_NEW};
-use crate::version::NodeVersion;
-
-use crate::version::Version;
-use crate::misc::HOME;
-
-// TODO: enforce this for all file reads
-const MAX_NVMRC_FILE_SIZE: u64 = 32;
-
-/// Descends down from `starting_point` searching for an
-/// `.nvmrc` file and stops at the first one that it finds
-pub async fn find_nvmrc<T: AsRef<Path>>(starting_point: T) -> Option<PathBuf> {
-    let mut path = starting_point.as_ref().to_path_buf();
-
-    if !path.is_absolute() {
-        return None;
-    }
-
-    // Remove the end if it's not a directory
-    if let Ok(metadata) = fs::metadata(&path).await {
-        if !metadata.is_dir() {
-            path.pop();
-        }
-    } else {
-        return None;
-    }
-
-    let mut stop_searching = false;
-    loop {
-        path.push(".nvmrc");
+use crate::misc::{list_all_nvm_versions, ListingType, nvm_path, Runtime, NVM_ALIAS_DIR};
+use crate::version::{NodeVersion, VersionSpec, find_version_of, find_version_req};
+use crate::index::resolve_remote_version;
+use crate::mirror::Channel;
+use crate::version_req::VersionReq;
+
+/// Picks the newest installed version in an npm-style range
+pub async fn resolve_version_req(version_req: &VersionReq) -> Result<NodeVersion> {
+    // None means that a matching release needs to be installed
+    Ok(NodeVersion::NvmVersion(find_version_req(version_req).await.ok()))
+}
 
-        let size = match fs::metadata(&path).await {
-            Ok(metadata) => Some(metadata.size()),
-            _ => None,
-        };
+pub const LTS_STR_START: &str = "lts/";
+pub const MAX_RECURSIVE_DEREF: u32 = 5;
 
-        if let Some(size) = size {
-            if size <= MAX_NVMRC_FILE_SIZE {
-                return Some(path);
-            }
-            break;
-        }
+pub async fn resolve_nvmrc_version(contents: &str, recursion_depth: u32) -> Result<NodeVersion> {
+    resolve_nvmrc_version_chain(contents, recursion_depth, &mut vec![]).await
+}
 
-        path.pop();
-        path.pop();
+/// Same as `resolve_nvmrc_version`, but also records `contents` and
+/// every alias it dereferences into `chain`, e.g. `work` pointing
+/// to `lts/iron` results in `["work", "lts/iron", "v20.11.0"]`
+#[async_recursion(?Send)]
+pub async fn resolve_nvmrc_version_chain(contents: &str, recursion_depth: u32, chain: &mut Vec<String>) -> Result<NodeVersion> {
+    let trimmed_contents = contents.trim();
 
-        if stop_searching { break }
-        if path.parent().is_none() {
-            // We've reached the root, so just search one more
-            // time
-            stop_searching = true;
-        }
+    if chain.iter().any(|link| link == trimmed_contents) {
+        chain.push(trimmed_contents.to_string());
+        return Err(anyhow!("alias cycle {}", chain.join(" -> ")));
     }
+    chain.push(trimmed_contents.to_string());
 
-    return None;
-}
-
-const LTS_STR_START: &str = "lts/";
-const MAX_RECURSIVE_DEREF: u32 = 5;
-
-#[async_recursion(?Send)]
-pub async fn resolve_nvmrc_version(contents: &str, recursion_depth: u32) -> Result<NodeVersion> {
     if recursion_depth > MAX_RECURSIVE_DEREF {
         return Err(anyhow!("max nvmrc recursive lookup reached"));
     }
 
-    let trimmed_contents = contents.trim();
-    let home = HOME.as_str();
+    let alias_dir = nvm_path(NVM_ALIAS_DIR);
 
-    if trimmed_contents.starts_with(LTS_STR_START) {
-        let lts_str_len = LTS_STR_START.len();
-        match trimmed_contents.get(lts_str_len..lts_str_len + 1) {
+    if let Some(lts_name) = trimmed_contents.strip_prefix(LTS_STR_START) {
+        match lts_name.get(0..1) {
             // Relative LTS number (in the form of "lts/-N" with N being an integer)
             Some("-") => {
-                let offset: usize = trimmed_contents[lts_str_len + 1..]
+                let offset: usize = lts_name[1..]
                     .parse()
                     .context("invalid relative nvmrc version")?;
-                let nvm_lts_aliases = fs::read_dir(format!("{}/.nvm/alias/lts", home))
-                    .await
-                    .context("nvm LTS aliases not found (invalid nvm install?)")?;
+                let nvm_lts_aliases = match fs::read_dir(format!("{}/lts", alias_dir)).await {
+                    Ok(nvm_lts_aliases) => nvm_lts_aliases,
+                    // nvm only creates the LTS aliases once it has fetched
+                    // the list of releases, so ask the mirror instead
+                    Err(_) => return resolve_remote_nvmrc_version(trimmed_contents).await,
+                };
                 let mut nvm_lts_aliases = ReadDirStream::new(nvm_lts_aliases)
-                    .filter_map(|dir| dir.map(|dir| Some(dir)).unwrap_or(None))
+                    .filter_map(|dir| dir.map(Some).unwrap_or(None))
                     .filter(|dir| dir.file_name().to_str().unwrap() != "*")
-                    .then(|dir| fs::read_to_string(format!("{}/.nvm/alias/lts/{}", home, dir.file_name().into_string().unwrap())))
+                    .then(|dir| fs::read_to_string(format!("{}/lts/{}", alias_dir, dir.file_name().into_string().unwrap())))
                     .filter_map(|version_str| version_str
-                        .map(|string| string.trim().parse::<Version>().map(|version| Some(version)).unwrap_or(None))
+                        .map(|string| string.trim().parse::<VersionSpec>().map(|spec| Some(spec.version)).unwrap_or(None))
                         .unwrap_or(None))
                     .collect::<Vec<_>>()
                     .await;
 
-                nvm_lts_aliases.sort_by(|a, b| a.partial_cmp(b).unwrap().reverse());
-                return nvm_lts_aliases
-                    .get(offset)
-                    .map(|version| Ok(NodeVersion::NvmVersion(Some(version.clone()))))
-                    .unwrap_or(Err(anyhow!("relative LTS version not found")));
+                nvm_lts_aliases.sort_by(|a, b| b.cmp(a));
+                nvm_lts_aliases.dedup();
+                return match nvm_lts_aliases.get(offset) {
+                    Some(version) => Ok(NodeVersion::NvmVersion(Some(version.clone()))),
+                    None => resolve_remote_nvmrc_version(trimmed_contents)
+                        .await
+                        .context("relative LTS version not found"),
+                };
             },
             // Normal LTS alias
             _ => {
-                let lts_name = trimmed_contents[LTS_STR_START.len()..].trim();
-                let path = format!("{}/.nvm/alias/lts/{}", home, lts_name);
-                return resolve_nvmrc_version(fs::read_to_string(path).await?.as_str(), recursion_depth + 1).await;
+                let path = format!("{}/lts/{}", alias_dir, lts_name.trim());
+                return match fs::read_to_string(path).await {
+                    Ok(contents) => resolve_nvmrc_version_chain(contents.as_str(), recursion_depth + 1, chain).await,
+                    Err(_) => resolve_remote_nvmrc_version(trimmed_contents).await,
+                };
             },
         }
     }
 
     match trimmed_contents {
-        "node" | "stable" => {
-            // Just sort the existing Node.js versions and find the latest
-            let mut versions = list_all_nvm_versions(ListingType::Both).await?;
-            versions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        "node" | "stable" | "unstable" => {
+            // Find the latest Node.js version, but like nvm, only even
+            // minor versions count as stable before v1
+            let wants_stable = trimmed_contents != "unstable";
+            let mut versions = list_all_nvm_versions(ListingType::Node).await?;
+            versions.retain(|version| version.pre.is_none() && version.is_stable() == wants_stable);
+            versions.sort();
             versions
                 .pop()
                 .map(|version| Ok(NodeVersion::NvmVersion(Some(version))))
@@ -123,37 +101,95 @@ pub async fn resolve_nvmrc_version(contents: &str, recursion_depth: u32) -> Resu
         "iojs" => {
             // Just sort the existing IO.js versions and find the latest
             let mut versions = list_all_nvm_versions(ListingType::Iojs).await?;
-            versions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            versions.retain(|version| version.pre.is_none());
+            versions.sort();
             versions
                 .pop()
                 .map(|version| Ok(NodeVersion::NvmVersion(Some(version))))
                 .unwrap_or(Ok(NodeVersion::NvmVersion(None))) // No IO.js versions found
         },
-        "default" => {
-            let path = format!("{}/.nvm/alias/default", home);
-            return resolve_nvmrc_version(fs::read_to_string(path).await?.as_str(), recursion_depth + 1).await;
+        "rc" | "nightly" => {
+            // The latest installed pre-release from the channel, these
+            // need their mirror to be configured to be installed
+            let channel = if trimmed_contents == "rc" { Channel::Rc } else { Channel::Nightly };
+            let mut versions = list_all_nvm_versions(ListingType::Node).await?;
+            versions.retain(|version| Channel::of(version) == channel);
+            versions.sort();
+            Ok(NodeVersion::NvmVersion(versions.pop()))
         },
         "system" => {
             return Ok(NodeVersion::System);
         },
         _ => {
-            // Try to parse a Version struct
-            trimmed_contents
+            // Aliases created with `nvm alias NAME VERSION` (including
+            // `default`) take precedence over everything else like in nvm
+            if !trimmed_contents.is_empty() && !trimmed_contents.contains('/') {
+                let path = format!("{}/{}", alias_dir, trimmed_contents);
+                if let Ok(alias_contents) = fs::read_to_string(path).await {
+                    return resolve_nvmrc_version_chain(alias_contents.as_str(), recursion_depth + 1, chain).await;
+                }
+            }
+
+            // Try to parse a version, which can name its runtime like `iojs-v3.3.1`
+            if let Ok(spec) = trimmed_contents.parse::<VersionSpec>() {
+                let runtime = spec.runtime();
+                let mut version = spec.version;
+                if version.is_full() {
+                    version.location = match find_version_of(&version, runtime.into()).await {
+                        Ok(installed_version) => installed_version.location,
+                        Err(_) => Some(PathBuf::from(format!("{}/{}", nvm_path(runtime.install_dir()), version))),
+                    };
+                    return Ok(NodeVersion::NvmVersion(Some(version)));
+                }
+
+                // Partial versions only match installs of their own runtime,
+                // so `node/3` never picks io.js v3. When it's named but nothing
+                // matches it's left to be installed like `node`/`iojs` are
+                return match find_version_of(&version, runtime.into()).await {
+                    Ok(installed_version) => Ok(NodeVersion::NvmVersion(Some(installed_version))),
+                    Err(_) if spec.runtime.is_some() => Ok(NodeVersion::NvmVersion(None)),
+                    Err(_) => Ok(NodeVersion::NvmVersion(Some(version))),
+                };
+            }
+
+            // Otherwise it might be a range like `^18.17` or `>=20`
+            if !has_version(trimmed_contents) {
+                return Err(anyhow!("invalid nvmrc version '{}'", trimmed_contents));
+            }
+            let version_req: VersionReq = trimmed_contents
                 .parse()
-                .map(|mut version| {
-                    let location = format!("{}/{}/{}", HOME.as_str(), get_runtime_install_dir(&version), version.to_string());
-                    version.location = Some(PathBuf::from(location));
-                    NodeVersion::NvmVersion(Some(version))
-                })
-                .context("invalid nvmrc version")
+                .context("invalid nvmrc version")?;
+            resolve_version_req(&version_req).await
         }
     }
 }
 
-fn get_runtime_install_dir(version: &Version) -> &'static str {
-    if is_iojs(version) {
-        NVM_VERSION_DIR_NEW_IOJS
-    } else {
-        NVM_VERSION_DIR_NEW
-    }
+/// npm reads an empty range (or just `v`/`=`) as any
+/// version, but nvm rejects those
+fn has_version(range: &str) -> bool {
+    range.contains(|chr: char| chr.is_ascii_digit() || matches!(chr, 'x' | 'X' | '*'))
+}
+
+/// Whether `arg` names a version rather than being any other word, i.e.
+/// it's a version, a range, one of nvm's keywords or an existing alias
+pub fn is_version_like(arg: &str) -> bool {
+    let is_keyword = matches!(arg, "node" | "stable" | "unstable" | "iojs" | "rc" | "nightly" | "system")
+        || arg.starts_with(LTS_STR_START);
+    let is_alias = !arg.is_empty()
+        && !arg.contains('/')
+        && Path::new(&format!("{}/{}", nvm_path(NVM_ALIAS_DIR), arg)).is_file();
+
+    is_keyword
+        || is_alias
+        || arg.parse::<VersionSpec>().is_ok()
+        || (has_version(arg) && arg.parse::<VersionReq>().is_ok())
+}
+
+/// Resolves a version string that nvm hasn't got the aliases for
+/// locally with the mirror's index of releases
+async fn resolve_remote_nvmrc_version(contents: &str) -> Result<NodeVersion> {
+    let mut version = resolve_remote_version(contents).await?;
+    let location = format!("{}/{}", nvm_path(Runtime::of(&version).install_dir()), version);
+    version.location = Some(PathBuf::from(location));
+    Ok(NodeVersion::NvmVersion(Some(version)))
 }
@@ -28,7 +28,6 @@ pub async fn npm_version(nvm_node_version: Option<&Version>) -> Result<Version>
             .as_str()
             .split(":")
             .map(|path| {
-                let path = path.clone();
                 async move {
                     // TODO: remove the double `canonicalize` call for system NPM
                     canonicalize(format!("{}/npm", path)).await
@@ -65,11 +64,10 @@ pub async fn system_node_version() -> Option<Version> {
 
     command
         .ok()
-        .map(|output| {
+        .and_then(|output| {
             String::from_utf8(output.stdout)
                 .map(|version_str| version_str.trim().parse().ok())
                 .ok()
                 .flatten()
         })
-        .flatten()
 }
@@ -0,0 +1,105 @@
+use std::env::var as get_env_var;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::copy;
+use std::path::Path;
+use std::process::Stdio;
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::process::Command;
+use tokio::task::spawn_blocking;
+use crate::mirror::download;
+
+const SHASUMS_FILE: &str = "SHASUMS256.txt";
+
+/// Whether `artifact_name` has a checksum in a release's `SHASUMS256.txt`
+pub fn is_listed(shasums: &str, artifact_name: &str) -> bool {
+    find_hash(shasums, artifact_name).is_some()
+}
+
+/// Checks a downloaded release artifact against the checksums from
+/// `fetch_shasums`
+pub async fn verify_artifact(shasums: &str, artifact_path: &Path) -> Result<()> {
+    let artifact_name = artifact_path.file_name().unwrap().to_str().unwrap();
+    let expected_hash = find_hash(shasums, artifact_name)
+        .ok_or(anyhow!("{} isn't listed in {}", artifact_name, SHASUMS_FILE))?;
+
+    let actual_hash = sha256_file(artifact_path).await?;
+    if actual_hash != expected_hash {
+        return Err(anyhow!(
+            "checksum mismatch for {} (expected {}, got {})",
+            artifact_name,
+            expected_hash,
+            actual_hash
+        ));
+    }
+
+    Ok(())
+}
+
+fn find_hash(shasums: &str, artifact_name: &str) -> Option<String> {
+    shasums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim() == artifact_name)
+        .map(|(hash, _)| hash.to_lowercase())
+}
+
+/// Downloads the `SHASUMS256.txt` published with a release. If
+/// `QUICKNVM_KEYRING` points to a GPG keyring with the release keys,
+/// the checksums themselves must also be signed by one of them
+pub async fn fetch_shasums(release_url: &str, work_dir: &Path) -> Result<String> {
+    let shasums_path = work_dir.join(SHASUMS_FILE);
+    download(&format!("{}/{}", release_url, SHASUMS_FILE), &shasums_path).await?;
+
+    let keyring = match get_env_var("QUICKNVM_KEYRING") {
+        Ok(keyring) if !keyring.is_empty() => keyring,
+        _ => return Ok(fs::read_to_string(&shasums_path).await?),
+    };
+
+    // Prefer the detached signature, but older releases only
+    // have a clearsigned copy of the checksums
+    let signature_path = work_dir.join(format!("{}.sig", SHASUMS_FILE));
+    if download(&format!("{}/{}.sig", release_url, SHASUMS_FILE), &signature_path).await.is_ok() {
+        run_gpgv(&keyring, &[signature_path.as_os_str(), shasums_path.as_os_str()]).await?;
+        return Ok(fs::read_to_string(&shasums_path).await?);
+    }
+
+    let clearsigned_path = work_dir.join(format!("{}.asc", SHASUMS_FILE));
+    download(&format!("{}/{}.asc", release_url, SHASUMS_FILE), &clearsigned_path)
+        .await
+        .context("no signature found for the release checksums")?;
+    let verified_path = work_dir.join(format!("{}.verified", SHASUMS_FILE));
+    run_gpgv(&keyring, &[OsStr::new("--output"), verified_path.as_os_str(), clearsigned_path.as_os_str()]).await?;
+
+    Ok(fs::read_to_string(&verified_path).await?)
+}
+
+async fn run_gpgv(keyring: &str, args: &[&OsStr]) -> Result<()> {
+    let output = Command::new("gpgv")
+        .arg("--keyring")
+        .arg(keyring)
+        .args(args)
+        .stdout(Stdio::null())
+        .output()
+        .await
+        .context("failed to run `gpgv` to check the release signature")?;
+
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("bad signature for {}: {}", SHASUMS_FILE, reason.trim()));
+    }
+
+    Ok(())
+}
+
+async fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let hash = spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        copy(&mut file, &mut hasher).map(|_| hasher.finalize())
+    }).await??;
+
+    Ok(format!("{:x}", hash))
+}
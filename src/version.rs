@@ -1,27 +1,36 @@
 use std::str::{FromStr, Chars};
+use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::path::PathBuf;
 use anyhow::{Result, anyhow};
-use tokio::{join, fs};
+use tokio::fs;
+use futures_util::future::join_all;
 use crate::misc::{
     list_all_nvm_versions,
     ListingType,
-    NVM_VERSION_DIR_OLD,
-    NVM_VERSION_DIR_NEW,
-    NVM_VERSION_DIR_OLD_IOJS,
-    NVM_VERSION_DIR_NEW_IOJS,
-    DOT_NVM_HOME,
-    HOME,
+    nvm_path,
+    has_node_binary,
+    Runtime,
 };
+use crate::env_utils::is_nvm_path;
+use crate::version_req::VersionReq;
 
 const INVALID_VERSION_STRING: &str = "invalid version";
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+/// Versions are compared by their version number and pre-release
+/// alone, so the same version installed in different places (or
+/// with different build metadata) is equal. Missing minor and patch
+/// versions count as 0
+#[derive(Clone, Debug, Default)]
 pub struct Version {
     pub major: u32,
     pub minor: Option<u32>,
     pub patch: Option<u32>,
+    /// The pre-release part, e.g. `rc.2` in `v21.0.0-rc.2`
+    pub pre: Option<String>,
+    /// Build metadata after a `+`, ignored when comparing versions
+    pub build: Option<String>,
     pub location: Option<PathBuf>,
 }
 
@@ -37,50 +46,113 @@ impl Version {
         self.minor.is_some() && self.patch.is_some()
     }
 
+    /// Before v1 odd minor versions were unstable releases,
+    /// every release since then is stable
+    pub fn is_stable(&self) -> bool {
+        self.major >= 1 || self.minor.unwrap_or(0).is_multiple_of(2)
+    }
+
+    /// Pre-releases only match when they're asked for
+    /// explicitly, so `v21` never picks `v21.0.0-rc.2`
     pub fn matches(&self, other: &Self) -> bool {
-        if self.major != other.major {
-            false
-        } else if self.minor.is_none() || other.minor.is_none() {
-            true
-        } else if self.minor.as_ref().unwrap() == other.minor.as_ref().unwrap() {
-            if self.patch.is_none() || other.patch.is_none() {
-                true
-            } else if self.patch.as_ref().unwrap() == other.patch.as_ref().unwrap() {
-                true
-            } else {
-                false
-            }
-        } else {
-            false
+        if self.major != other.major || self.pre != other.pre {
+            return false;
+        }
+
+        match (self.minor, other.minor) {
+            (Some(minor), Some(other_minor)) if minor == other_minor => {
+                match (self.patch, other.patch) {
+                    (Some(patch), Some(other_patch)) => patch == other_patch,
+                    _ => true,
+                }
+            },
+            (Some(_), Some(_)) => false,
+            _ => true,
         }
     }
 }
 
-impl Default for Version {
-    fn default() -> Self {
-        Version {
-            major: 0,
-            minor: None,
-            patch: None,
-            location: None
+/// Compares pre-release parts with semver's precedence rules, a
+/// release is always newer than any of its pre-releases
+fn cmp_pre(pre: &Option<String>, other_pre: &Option<String>) -> Ordering {
+    let (pre, other_pre) = match (pre, other_pre) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) => return Ordering::Greater,
+        (Some(_), None) => return Ordering::Less,
+        (Some(pre), Some(other_pre)) => (pre, other_pre),
+    };
+
+    let mut identifiers = pre.split('.');
+    let mut other_identifiers = other_pre.split('.');
+    loop {
+        let ordering = match (identifiers.next(), other_identifiers.next()) {
+            (None, None) => return Ordering::Equal,
+            // More identifiers means a newer pre-release
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(identifier), Some(other_identifier)) => {
+                match (identifier.parse::<u64>(), other_identifier.parse::<u64>()) {
+                    (Ok(number), Ok(other_number)) => number.cmp(&other_number),
+                    // Numeric identifiers are older than alphanumeric ones
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => identifier.cmp(other_identifier),
+                }
+            },
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
         }
     }
 }
 
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0))
+            .cmp(&(other.major, other.minor.unwrap_or(0), other.patch.unwrap_or(0)))
+            .then_with(|| cmp_pre(&self.pre, &other.pre))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
 impl Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut output = self.major.to_string();
 
         if let Some(minor) = self.minor {
-            output.push_str(".");
+            output.push('.');
             output.push_str(minor.to_string().as_str());
 
             if let Some(patch) = self.patch {
-                output.push_str(".");
+                output.push('.');
                 output.push_str(patch.to_string().as_str());
             }
         }
 
+        if let Some(ref pre) = self.pre {
+            output.push('-');
+            output.push_str(pre);
+        }
+
+        if let Some(ref build) = self.build {
+            output.push('+');
+            output.push_str(build);
+        }
+
         write!(f, "v{}", output)
     }
 }
@@ -111,20 +183,32 @@ impl FromStr for Version {
             version_str = &version_str[1..];
         }
 
-        if version_str.len() == 0 {
+        // Split off `+build` first since pre-releases can contain `-`
+        let (version_str, build) = match version_str.split_once('+') {
+            Some((version_str, build)) => (version_str, Some(build.to_string())),
+            None => (version_str, None),
+        };
+        let (version_str, pre) = match version_str.split_once('-') {
+            Some((version_str, pre)) => (version_str, Some(pre.to_string())),
+            None => (version_str, None),
+        };
+
+        if version_str.is_empty()
+            || pre.as_deref().is_some_and(str::is_empty)
+            || build.as_deref().is_some_and(str::is_empty) {
             return Err(ParseVersionError);
         }
 
         fn get_number_digits(iter: &mut Chars) -> Option<String> {
             let mut number = String::new();
-            while let Some(digit) = iter.next() {
+            for digit in iter.by_ref() {
                 match digit {
                     '.' => break,
                     _ => number.push(digit),
                 }
             }
 
-            if number.len() > 0 {
+            if !number.is_empty() {
                 Some(number)
             } else {
                 None
@@ -146,60 +230,143 @@ impl FromStr for Version {
                 .map_err(|_| ParseVersionError))
             .transpose()?;
 
+        // Only full versions can have a pre-release or build
+        if (pre.is_some() || build.is_some()) && patch.is_none() {
+            return Err(ParseVersionError);
+        }
+
         Ok(Version {
             major,
             minor,
             patch,
+            pre,
+            build,
             location: None
         })
     }
 }
 
+/// A version string that can name its runtime like nvm allows,
+/// e.g. `iojs-v3.3.1`, `iojs-3` or `node/v18`
+#[derive(Clone, Debug)]
+pub struct VersionSpec {
+    /// None if the string was just a version
+    pub runtime: Option<Runtime>,
+    pub version: Version,
+}
+
+impl VersionSpec {
+    /// The runtime named in the spec, otherwise the
+    /// one that released this version
+    pub fn runtime(&self) -> Runtime {
+        self.runtime.unwrap_or_else(|| Runtime::of(&self.version))
+    }
+}
+
+impl FromStr for VersionSpec {
+    type Err = ParseVersionError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (runtime, version_str) = match spec.split_once(['-', '/']) {
+            Some(("iojs", version_str)) => (Some(Runtime::Iojs), version_str),
+            Some(("node", version_str)) => (Some(Runtime::Node), version_str),
+            _ => (None, spec),
+        };
+
+        Ok(VersionSpec {
+            runtime,
+            version: version_str.parse()?,
+        })
+    }
+}
+
+impl Display for VersionSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.runtime {
+            Some(Runtime::Iojs) => write!(f, "iojs-{}", self.version),
+            _ => write!(f, "{}", self.version),
+        }
+    }
+}
+
+/// A version's directory exists, but it doesn't have an executable
+/// `bin/node` (usually because an install was aborted)
+#[derive(Debug)]
+pub struct BrokenInstall {
+    /// The broken version, with the location of its directory
+    pub version: Version,
+}
+
+impl Display for BrokenInstall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in '{}' is broken, it has no executable bin/node",
+            self.version,
+            self.version.location.as_ref().unwrap().display(),
+        )
+    }
+}
+
+impl Error for BrokenInstall {}
+
 /// Checks if an NVM-managed Node version is installed
 pub async fn find_version(version: &Version) -> Result<Version> {
+    find_version_of(version, ListingType::Both).await
+}
+
+/// Checks if an NVM-managed version is installed, only looking
+/// at the versions of a runtime when it's known
+pub async fn find_version_of(version: &Version, listing_type: ListingType) -> Result<Version> {
     if version.is_full() {
         let version_string = version.to_string();
-        let mut owned_version = version.clone();
-
-        // TODO: See if there's a nice way to deduplicate all of this
-        let paths = [
-            format!("{}/{}/{}", HOME.as_str(), NVM_VERSION_DIR_OLD, version_string),
-            format!("{}/{}/{}", HOME.as_str(), NVM_VERSION_DIR_NEW, version_string),
-            format!("{}/{}/{}", HOME.as_str(), NVM_VERSION_DIR_OLD_IOJS, version_string),
-            format!("{}/{}/{}", HOME.as_str(), NVM_VERSION_DIR_NEW_IOJS, version_string),
-        ];
+        // Where the version is expected to be (e.g. the directory
+        // of the runtime an `.nvmrc` asked for) is checked first
+        let paths = version.location
+            .iter()
+            .cloned()
+            .chain(listing_type
+                .dirs()
+                .into_iter()
+                .map(|dir| PathBuf::from(format!("{}/{}", nvm_path(dir), version_string))))
+            .collect::<Vec<_>>();
+
         // Search all possible locations concurrently
-        let possible_version_paths = join!(
-            fs::metadata(&paths[0]),
-            fs::metadata(&paths[1]),
-            fs::metadata(&paths[2]),
-            fs::metadata(&paths[3]),
-        );
-        let possible_version_paths = [
-            possible_version_paths.0,
-            possible_version_paths.1,
-            possible_version_paths.2,
-            possible_version_paths.3,
-        ];
-
-        for (index, version) in possible_version_paths.iter().enumerate() {
-            if let Ok(dir) = version {
-                if dir.is_dir() {
-                    let _ = owned_version.location.insert(PathBuf::from(&paths[index]));
+        let possible_version_paths = join_all(paths.iter().map(fs::metadata)).await;
+        let mut broken_install = None;
+        for (path, metadata) in paths.into_iter().zip(possible_version_paths) {
+            if metadata.is_ok_and(|dir| dir.is_dir()) {
+                let mut owned_version = version.clone();
+                let has_node_binary = has_node_binary(&path).await;
+                owned_version.location = Some(path);
+
+                if has_node_binary {
                     return Ok(owned_version);
                 }
+                broken_install.get_or_insert(owned_version);
             }
         }
 
-        Err(anyhow!("couldn't find version {}", version))
+        match broken_install {
+            Some(version) => Err(BrokenInstall { version }.into()),
+            None => Err(anyhow!("couldn't find version {}", version)),
+        }
     } else {
-        let mut versions = list_all_nvm_versions(ListingType::Both).await?;
+        let mut versions = list_all_nvm_versions(listing_type).await?;
         versions.retain(|version_entry| version.matches(version_entry));
-        versions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        versions.sort();
         versions.pop().ok_or(anyhow!("couldn't find version {}", version))
     }
 }
 
+/// Finds the newest NVM-managed Node version in an npm-style range
+pub async fn find_version_req(version_req: &VersionReq) -> Result<Version> {
+    let mut versions = list_all_nvm_versions(ListingType::Both).await?;
+    versions.retain(|version| version_req.matches(version));
+    versions.sort();
+    versions.pop().ok_or(anyhow!("couldn't find a version matching {}", version_req))
+}
+
 /// Checks to see what the current version is from
 /// the PATH env variable. Returning None implies the
 /// "system" version from NVM—i.e. there's no NVM-managed
@@ -207,25 +374,95 @@ pub async fn find_version(version: &Version) -> Result<Version> {
 pub fn find_current_version(path: &str) -> Option<Version> {
     let nvm_path_dirs = path
         .split(":")
-        .filter(|path| path.starts_with(DOT_NVM_HOME.as_str()))
+        .filter(|path| is_nvm_path(path))
         .map(|path| path.to_string())
         .next();
 
     if let Some(path) = nvm_path_dirs {
         let mut components: Vec<&str> = path
             .split("/")
-            .filter(|component| component.len() > 0)
+            .filter(|component| !component.is_empty())
             .collect();
         components.pop();
 
-        return components
+        components
             .pop()
-            .map_or(None, |version| version.parse()
+            .and_then(|version| version.parse()
                 .map_or(None, |mut version: Version| {
                     version.location = Some(PathBuf::from(&path[..path.len() - 4]));
                     Some(version)
-                }));
+                }))
     } else {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sorted oldest first, the same order node-semver's `compare` gives
+    const ORDERED: &[&str] = &[
+        "0.10.48",
+        "1.0.0-alpha",
+        "1.0.0-alpha.1",
+        "1.0.0-alpha.beta",
+        "1.0.0-beta",
+        "1.0.0-beta.2",
+        "1.0.0-beta.11",
+        "1.0.0-rc.1",
+        "1.0.0",
+        "1.9.0",
+        "1.10.0",
+        "21.0.0-nightly20230901abcdef",
+        "21.0.0-rc.1",
+        "21.0.0",
+    ];
+
+    fn version(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    #[test]
+    fn orders_like_node_semver() {
+        for (i, older) in ORDERED.iter().enumerate() {
+            for newer in &ORDERED[i + 1..] {
+                assert!(version(older) < version(newer), "{} < {}", older, newer);
+            }
+        }
+    }
+
+    #[test]
+    fn compares_pre_releases() {
+        let pre = |pre: &str| Some(pre.to_string());
+        assert_eq!(cmp_pre(&None, &None), Ordering::Equal);
+        assert_eq!(cmp_pre(&None, &pre("rc.1")), Ordering::Greater);
+        assert_eq!(cmp_pre(&pre("rc.1"), &pre("rc.1")), Ordering::Equal);
+        assert_eq!(cmp_pre(&pre("rc.2"), &pre("rc.10")), Ordering::Less);
+        assert_eq!(cmp_pre(&pre("rc"), &pre("rc.1")), Ordering::Less);
+        assert_eq!(cmp_pre(&pre("1"), &pre("alpha")), Ordering::Less);
+    }
+
+    #[test]
+    fn ignores_location_and_build() {
+        let mut installed = version("18.17.1");
+        installed.location = Some(PathBuf::from("/nvm/versions/node/v18.17.1"));
+        assert_eq!(installed, version("v18.17.1"));
+        assert_eq!(version("18.17.1+build.5"), version("18.17.1"));
+        assert_eq!(version("18"), version("18.0.0"));
+    }
+
+    #[test]
+    fn sorts_installs_from_different_layouts() {
+        let mut old_layout = version("0.10.48");
+        old_layout.location = Some(PathBuf::from("/nvm/v0.10.48"));
+        let mut new_layout = version("20.11.0");
+        new_layout.location = Some(PathBuf::from("/nvm/versions/node/v20.11.0"));
+
+        let mut versions = vec![new_layout.clone(), version("18.17.1"), old_layout.clone()];
+        versions.sort();
+        assert_eq!(versions, vec![version("0.10.48"), version("18.17.1"), version("20.11.0")]);
+        assert!(versions.contains(&new_layout));
+        assert_eq!(versions.iter().max(), Some(&new_layout));
+    }
+}
@@ -0,0 +1,292 @@
+use std::env::var as get_env_var;
+use std::os::unix::prelude::MetadataExt;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tokio::fs;
+use crate::nvmrc::{resolve_nvmrc_version_chain, resolve_version_req};
+use crate::version::NodeVersion;
+
+// TODO: enforce this for all file reads
+const MAX_NVMRC_FILE_SIZE: u64 = 32;
+const MAX_CONFIG_FILE_SIZE: u64 = 1024 * 1024;
+
+/// The files that are looked for by default, a file earlier in
+/// the list wins if there's more than one in the same directory
+const DEFAULT_VERSION_FILES: &str = ".nvmrc,.node-version,.tool-versions,mise.toml,.mise.toml,package.json";
+
+/// The kinds of files that can pick a version
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VersionFileKind {
+    Nvmrc,
+    /// `.node-version` from nodenv, fnm and others
+    NodeVersion,
+    /// asdf's `.tool-versions`
+    ToolVersions,
+    /// The `[tools]` table of `mise.toml` or `.mise.toml`
+    Mise,
+    /// The `engines.node` range of a package.json
+    PackageJson,
+}
+
+impl VersionFileKind {
+    fn from_file_name(file_name: &str) -> Option<Self> {
+        match file_name {
+            ".nvmrc" => Some(VersionFileKind::Nvmrc),
+            ".node-version" => Some(VersionFileKind::NodeVersion),
+            ".tool-versions" => Some(VersionFileKind::ToolVersions),
+            "mise.toml" | ".mise.toml" => Some(VersionFileKind::Mise),
+            "package.json" => Some(VersionFileKind::PackageJson),
+            _ => None,
+        }
+    }
+
+    fn max_file_size(&self) -> u64 {
+        match self {
+            VersionFileKind::Nvmrc | VersionFileKind::NodeVersion => MAX_NVMRC_FILE_SIZE,
+            _ => MAX_CONFIG_FILE_SIZE,
+        }
+    }
+}
+
+/// A file that picks which version to use
+pub struct VersionFile {
+    pub path: PathBuf,
+    pub kind: VersionFileKind,
+    /// The version string from the file, e.g. `lts/iron` from an
+    /// `.nvmrc` or `>=18.12 <21` from a package.json
+    pub version: String,
+}
+
+#[derive(Deserialize)]
+struct PackageJson {
+    engines: Option<PackageJsonEngines>,
+}
+
+#[derive(Deserialize)]
+struct PackageJsonEngines {
+    node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MiseToml {
+    tools: Option<toml::Table>,
+}
+
+impl VersionFile {
+    /// Reads the version from the file called `file_name` in `dir`.
+    /// Returns Ok(None) if there's no such file or it doesn't pick a
+    /// version, and an error if the file exists but can't be used
+    async fn read(dir: &Path, file_name: &str, kind: VersionFileKind) -> Result<Option<Self>> {
+        let path = dir.join(file_name);
+        let size = match fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => metadata.size(),
+            _ => return Ok(None),
+        };
+
+        if size > kind.max_file_size() {
+            return match kind {
+                VersionFileKind::Nvmrc | VersionFileKind::NodeVersion => {
+                    Err(anyhow!("{} is too big", path.display()))
+                },
+                _ => Ok(None),
+            };
+        }
+
+        let contents = fs::read_to_string(&path).await?;
+        let version = match kind {
+            VersionFileKind::Nvmrc | VersionFileKind::NodeVersion => Some(contents.trim().to_string()),
+            VersionFileKind::ToolVersions => parse_tool_versions(&contents),
+            // Config files that don't mention Node.js (or are broken) are
+            // common enough that they shouldn't stop the search
+            VersionFileKind::Mise => parse_mise_toml(&contents),
+            VersionFileKind::PackageJson => serde_json::from_str::<PackageJson>(&contents)
+                .ok()
+                .and_then(|package_json| package_json.engines?.node)
+                .map(|range| range.trim().to_string()),
+        };
+
+        Ok(version.map(|version| VersionFile {
+            path,
+            kind,
+            version,
+        }))
+    }
+
+    /// Resolves the version from the file, recording any
+    /// aliases that were followed in `alias_chain`
+    pub async fn resolve(&self, alias_chain: &mut Vec<String>) -> Result<NodeVersion> {
+        match self.kind {
+            VersionFileKind::PackageJson => resolve_version_req(&self.version.parse()?).await,
+            _ => resolve_nvmrc_version_chain(&self.version, 0, alias_chain).await,
+        }
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.path.file_name().unwrap().to_str().unwrap()
+    }
+}
+
+/// Translates the version names asdf and mise use
+/// into the nvm equivalents
+fn normalize_tool_version(version: &str) -> String {
+    match version {
+        "latest" => "node".to_string(),
+        "lts" => "lts/*".to_string(),
+        _ => match version.strip_prefix("lts-") {
+            Some(codename) => format!("lts/{}", codename),
+            None => version.to_string(),
+        },
+    }
+}
+
+/// `.tool-versions` has a line per tool, like `nodejs 18.17.0 16.20.0`,
+/// where later versions are fallbacks which nvm has no equivalent for
+fn parse_tool_versions(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("nodejs" | "node") => fields.next().map(normalize_tool_version),
+                _ => None,
+            }
+        })
+}
+
+/// mise accepts `node = "20"`, `node = ["20", "18"]` and
+/// `node = { version = "20" }` in its `[tools]` table
+fn parse_mise_toml(contents: &str) -> Option<String> {
+    let tools = toml::from_str::<MiseToml>(contents).ok()?.tools?;
+    let node = tools.get("node").or_else(|| tools.get("nodejs"))?;
+
+    let version = match node {
+        toml::Value::String(version) => version.as_str(),
+        toml::Value::Array(versions) => versions.first()?.as_str()?,
+        toml::Value::Table(options) => options.get("version")?.as_str()?,
+        _ => return None,
+    };
+
+    Some(normalize_tool_version(version.trim()))
+}
+
+/// The version files that are looked for, and which one wins when
+/// there's more than one in the same directory. Can be changed
+/// with `QUICKNVM_VERSION_FILES`, e.g. `package.json,.nvmrc` to
+/// prefer `engines` over `.nvmrc` or just `.nvmrc` to ignore the rest
+fn get_version_file_names() -> Vec<(String, VersionFileKind)> {
+    let version_files = get_env_var("QUICKNVM_VERSION_FILES")
+        .ok()
+        .filter(|version_files| !version_files.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_VERSION_FILES.to_string());
+
+    version_files
+        .split(',')
+        .map(|file_name| file_name.trim())
+        .filter_map(|file_name| match VersionFileKind::from_file_name(file_name) {
+            Some(kind) => Some((file_name.to_string(), kind)),
+            None => {
+                eprintln!("Ignoring unknown version file '{}' in QUICKNVM_VERSION_FILES", file_name);
+                None
+            },
+        })
+        .collect()
+}
+
+/// Descends down from `starting_point` searching for an `.nvmrc`
+/// (or other version file) and stops at the first one that it finds
+pub async fn find_version_file<T: AsRef<Path>>(starting_point: T) -> Option<VersionFile> {
+    let mut path = starting_point.as_ref().to_path_buf();
+
+    if !path.is_absolute() {
+        return None;
+    }
+
+    // Remove the end if it's not a directory
+    if let Ok(metadata) = fs::metadata(&path).await {
+        if !metadata.is_dir() {
+            path.pop();
+        }
+    } else {
+        return None;
+    }
+
+    let file_names = get_version_file_names();
+    loop {
+        for (file_name, kind) in file_names.iter() {
+            match VersionFile::read(&path, file_name, *kind).await {
+                Ok(Some(version_file)) => return Some(version_file),
+                Ok(None) => {},
+                Err(_) => return None,
+            }
+        }
+
+        // Stop once we've searched the root too
+        if !path.pop() {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tool_versions() {
+        let contents = "\
+# nodejs 16.20.0
+python 3.12.0
+nodejs 18.17.0 16.20.0 # the fallback is ignored
+";
+        assert_eq!(parse_tool_versions(contents).as_deref(), Some("18.17.0"));
+        assert_eq!(parse_tool_versions("node lts-iron\n").as_deref(), Some("lts/iron"));
+        assert_eq!(parse_tool_versions("#nodejs 20\nruby 3.3.0\n"), None);
+        assert_eq!(parse_tool_versions("nodejs\n"), None);
+    }
+
+    #[test]
+    fn parses_mise_toml() {
+        let cases = [
+            ("[tools]\nnode = \"20\"\n", Some("20")),
+            ("[tools]\nnode = [\"20\", \"18\"]\n", Some("20")),
+            ("[tools]\nnode = { version = \"lts-iron\" }\n", Some("lts/iron")),
+            ("[tools]\nnodejs = \"latest\"\n", Some("node")),
+            ("[tools]\nnode = \"lts\"\n", Some("lts/*")),
+            ("[tools]\npython = \"3.12\"\n", None),
+            ("[env]\nNODE_ENV = \"production\"\n", None),
+            ("[tools\nnode = \"20\"\n", None),
+        ];
+
+        for (contents, expected) in cases {
+            assert_eq!(parse_mise_toml(contents).as_deref(), expected, "{:?}", contents);
+        }
+    }
+
+    #[tokio::test]
+    async fn prefers_version_files_in_order() {
+        let dir = std::env::temp_dir().join(format!("quicknvm-version-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".nvmrc"), "18\n").unwrap();
+        std::fs::write(dir.join(".tool-versions"), "nodejs 19.0.0\n").unwrap();
+        std::fs::write(dir.join("package.json"), r#"{ "engines": { "node": ">=20" } }"#).unwrap();
+
+        // The only test that touches QUICKNVM_VERSION_FILES
+        let orders = [
+            ("", VersionFileKind::Nvmrc, "18"),
+            (".tool-versions,.nvmrc", VersionFileKind::ToolVersions, "19.0.0"),
+            ("package.json, .nvmrc", VersionFileKind::PackageJson, ">=20"),
+            ("mise.toml,.nvmrc", VersionFileKind::Nvmrc, "18"),
+        ];
+        for (version_files, kind, version) in orders {
+            std::env::set_var("QUICKNVM_VERSION_FILES", version_files);
+            let version_file = find_version_file(&dir).await.unwrap();
+            assert_eq!(version_file.kind, kind, "{:?}", version_files);
+            assert_eq!(version_file.version, version);
+        }
+
+        std::env::remove_var("QUICKNVM_VERSION_FILES");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use crate::version::Version;
+
+/// An npm-style version range like `>=18.12 <21`, `^16.14`, `18.x`
+/// or `14 || 16`, as used by the `engines` field of package.json
+#[derive(Clone, Debug)]
+pub struct VersionReq {
+    raw: String,
+    /// Any of these sets of comparators have to match
+    comparator_sets: Vec<Vec<Comparator>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Comparator {
+    op: Op,
+    version: (u32, u32, u32),
+}
+
+/// A version where any part can be missing or a wildcard, e.g. `18.x`
+#[derive(Clone, Copy)]
+struct Partial {
+    major: Option<u32>,
+    minor: Option<u32>,
+    patch: Option<u32>,
+}
+
+impl Comparator {
+    fn new(op: Op, version: (u32, u32, u32)) -> Self {
+        Comparator { op, version }
+    }
+
+    fn matches(&self, version: (u32, u32, u32)) -> bool {
+        match self.op {
+            Op::Lt => version < self.version,
+            Op::Le => version <= self.version,
+            Op::Gt => version > self.version,
+            Op::Ge => version >= self.version,
+        }
+    }
+}
+
+impl FromStr for Partial {
+    type Err = anyhow::Error;
+
+    fn from_str(partial: &str) -> Result<Self> {
+        let partial = partial.trim_start_matches(['v', '=']);
+        // Build metadata doesn't affect matching
+        let partial = partial.split('+').next().unwrap();
+        let mut parts = partial.split('.').map(|part| match part {
+            "x" | "X" | "*" | "" => Ok(None),
+            _ => part
+                .parse()
+                .map(Some)
+                .map_err(|_| anyhow!("invalid version '{}' in range", partial)),
+        });
+
+        let major = parts.next().transpose()?.flatten();
+        let minor = parts.next().transpose()?.flatten();
+        let patch = parts.next().transpose()?.flatten();
+        if parts.next().is_some() {
+            return Err(anyhow!("invalid version '{}' in range", partial));
+        }
+
+        // Anything after a wildcard is a wildcard too
+        Ok(Partial {
+            major,
+            minor: major.and(minor),
+            patch: major.and(minor).and(patch),
+        })
+    }
+}
+
+impl Partial {
+    fn lowest(&self) -> (u32, u32, u32) {
+        (self.major.unwrap_or(0), self.minor.unwrap_or(0), self.patch.unwrap_or(0))
+    }
+
+    /// The first version after everything this partial version
+    /// covers, None if it covers everything
+    fn next_excluded(&self) -> Option<(u32, u32, u32)> {
+        match (self.major, self.minor, self.patch) {
+            (Some(major), Some(minor), Some(patch)) => Some((major, minor, patch + 1)),
+            (Some(major), Some(minor), None) => Some((major, minor + 1, 0)),
+            (Some(major), None, _) => Some((major + 1, 0, 0)),
+            (None, _, _) => None,
+        }
+    }
+
+    /// `1.2` is `>=1.2.0 <1.3.0` and so on
+    fn x_range(&self) -> Vec<Comparator> {
+        let mut comparators = vec![];
+        if self.major.is_some() {
+            comparators.push(Comparator::new(Op::Ge, self.lowest()));
+        }
+        if let Some(upper) = self.next_excluded() {
+            comparators.push(Comparator::new(Op::Lt, upper));
+        }
+        comparators
+    }
+
+    /// `~1.2.3` is `>=1.2.3 <1.3.0`, `~1` is `>=1.0.0 <2.0.0`
+    fn tilde_range(&self) -> Vec<Comparator> {
+        match (self.major, self.minor) {
+            (Some(major), Some(minor)) => vec![
+                Comparator::new(Op::Ge, self.lowest()),
+                Comparator::new(Op::Lt, (major, minor + 1, 0)),
+            ],
+            _ => self.x_range(),
+        }
+    }
+
+    /// `^1.2.3` is `>=1.2.3 <2.0.0`, but the first non-zero part
+    /// is what can't change, so `^0.2.3` is `>=0.2.3 <0.3.0`
+    fn caret_range(&self) -> Vec<Comparator> {
+        let upper = match (self.major, self.minor, self.patch) {
+            (Some(0), Some(0), Some(patch)) => (0, 0, patch + 1),
+            (Some(0), Some(minor), _) => (0, minor + 1, 0),
+            (Some(major), _, _) => (major + 1, 0, 0),
+            (None, _, _) => return vec![],
+        };
+
+        vec![
+            Comparator::new(Op::Ge, self.lowest()),
+            Comparator::new(Op::Lt, upper),
+        ]
+    }
+
+    fn primitive(&self, op: Op) -> Vec<Comparator> {
+        match op {
+            Op::Ge => vec![Comparator::new(Op::Ge, self.lowest())],
+            Op::Lt => vec![Comparator::new(Op::Lt, self.lowest())],
+            // `>1.2` means anything after 1.2.x and `<=1.2` includes 1.2.x
+            Op::Gt => match self.next_excluded() {
+                Some(version) => vec![Comparator::new(Op::Ge, version)],
+                // Nothing is greater than everything
+                None => vec![Comparator::new(Op::Lt, (0, 0, 0))],
+            },
+            Op::Le => match self.next_excluded() {
+                Some(version) => vec![Comparator::new(Op::Lt, version)],
+                None => vec![],
+            },
+        }
+    }
+}
+
+/// Splits off the operator from a comparator like `>=1.2`
+fn split_operator(comparator: &str) -> (&str, &str) {
+    let operator_len = comparator
+        .find(|chr: char| !matches!(chr, '<' | '>' | '=' | '~' | '^'))
+        .unwrap_or(comparator.len());
+    comparator.split_at(operator_len)
+}
+
+fn parse_comparator(comparator: &str) -> Result<Vec<Comparator>> {
+    let (operator, version) = split_operator(comparator);
+    let partial: Partial = version.parse()?;
+
+    Ok(match operator {
+        "" | "=" => partial.x_range(),
+        "~" | "~>" => partial.tilde_range(),
+        "^" => partial.caret_range(),
+        ">" => partial.primitive(Op::Gt),
+        ">=" => partial.primitive(Op::Ge),
+        "<" => partial.primitive(Op::Lt),
+        "<=" => partial.primitive(Op::Le),
+        _ => return Err(anyhow!("invalid operator '{}' in range", operator)),
+    })
+}
+
+/// Parses a range without `||`
+fn parse_comparator_set(range: &str) -> Result<Vec<Comparator>> {
+    // Hyphen ranges: `1.2 - 2.3.4` is `>=1.2.0 <=2.3.4`
+    if let Some((lower, upper)) = range.split_once(" - ") {
+        let lower: Partial = lower.trim().parse()?;
+        let upper: Partial = upper.trim().parse()?;
+        let mut comparators = lower.primitive(Op::Ge);
+        comparators.append(&mut upper.primitive(Op::Le));
+        return Ok(comparators);
+    }
+
+    // npm allows spaces between operators and versions, e.g. `>= 1.2`
+    let mut comparator_strs: Vec<String> = vec![];
+    let mut pending_operator = String::new();
+    for token in range.split_whitespace() {
+        let (operator, version) = split_operator(token);
+        if version.is_empty() {
+            pending_operator.push_str(operator);
+        } else {
+            comparator_strs.push(format!("{}{}", pending_operator, token));
+            pending_operator.clear();
+        }
+    }
+    if !pending_operator.is_empty() {
+        return Err(anyhow!("missing version after '{}' in range", pending_operator));
+    }
+
+    let mut comparators = vec![];
+    for comparator in comparator_strs {
+        comparators.append(&mut parse_comparator(&comparator)?);
+    }
+    Ok(comparators)
+}
+
+impl FromStr for VersionReq {
+    type Err = anyhow::Error;
+
+    fn from_str(range: &str) -> Result<Self> {
+        let comparator_sets = range
+            .split("||")
+            .map(|range| parse_comparator_set(range.trim()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(VersionReq {
+            raw: range.trim().to_string(),
+            comparator_sets,
+        })
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl VersionReq {
+    /// Like npm, pre-releases never match a range
+    pub fn matches(&self, version: &Version) -> bool {
+        if version.pre.is_some() {
+            return false;
+        }
+
+        let version = (version.major, version.minor.unwrap_or(0), version.patch.unwrap_or(0));
+        self.comparator_sets
+            .iter()
+            .any(|comparators| comparators
+                .iter()
+                .all(|comparator| comparator.matches(version)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether a range matches a version, checked against node-semver's
+    /// `satisfies`
+    const MATCHES: &[(&str, &str, bool)] = &[
+        ("^0.0.3", "0.0.3", true),
+        ("^0.0.3", "0.0.4", false),
+        ("^0.0.3", "0.0.2", false),
+        ("^0.2.3", "0.2.9", true),
+        ("^0.2.3", "0.3.0", false),
+        ("^1.2.3", "1.9.9", true),
+        ("^1.2.3", "1.2.2", false),
+        ("^1.2.3", "2.0.0", false),
+        ("^1.2", "1.2.0", true),
+        ("^1.2", "2.0.0", false),
+        ("^0.0", "0.0.9", true),
+        ("^0.0", "0.1.0", false),
+        ("~1", "1.0.0", true),
+        ("~1", "1.9.9", true),
+        ("~1", "2.0.0", false),
+        ("~1.2", "1.2.9", true),
+        ("~1.2", "1.3.0", false),
+        ("~1.2.3", "1.2.3", true),
+        ("~1.2.3", "1.2.2", false),
+        ("~1.2.3", "1.3.0", false),
+        ("~>1.2", "1.2.5", true),
+        ("1.2 - 2.3", "1.2.0", true),
+        ("1.2 - 2.3", "1.1.9", false),
+        ("1.2 - 2.3", "2.3.9", true),
+        ("1.2 - 2.3", "2.4.0", false),
+        ("1.2.3 - 2.3.4", "2.3.4", true),
+        ("1.2.3 - 2.3.4", "2.3.5", false),
+        (">1.2", "1.2.9", false),
+        (">1.2", "1.3.0", true),
+        (">1.2.3", "1.2.4", true),
+        (">=1.2", "1.2.0", true),
+        (">=1.2", "1.1.9", false),
+        ("<1.2", "1.1.9", true),
+        ("<1.2", "1.2.0", false),
+        ("<=1.2", "1.2.9", true),
+        ("<=1.2", "1.3.0", false),
+        ("*", "0.0.0", true),
+        ("*", "21.1.0", true),
+        ("x", "18.0.0", true),
+        (">*", "1.0.0", false),
+        ("18.x", "18.19.0", true),
+        ("18.x", "19.0.0", false),
+        ("1.2.x", "1.2.7", true),
+        ("1.2.x", "1.3.0", false),
+        ("=1.2.3", "1.2.3", true),
+        ("v1.2.3", "1.2.3", true),
+        ("1.2.3", "1.2.4", false),
+        ("1.2.3+build.1", "1.2.3", true),
+        ("14 || 16", "14.1.0", true),
+        ("14 || 16", "15.0.0", false),
+        ("14 || 16", "16.2.0", true),
+        (">=18.12 <21", "18.12.0", true),
+        (">=18.12 <21", "20.9.0", true),
+        (">=18.12 <21", "21.0.0", false),
+        (">= 18.12 < 21", "18.11.0", false),
+        ("^18.0.0", "18.0.0-rc.1", false),
+        ("*", "21.0.0-rc.1", false),
+    ];
+
+    #[test]
+    fn matches_like_node_semver() {
+        for &(range, version, expected) in MATCHES {
+            let req: VersionReq = range.parse().unwrap();
+            let version: Version = version.parse().unwrap();
+            assert_eq!(req.matches(&version), expected, "{} matching {}", range, version);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        for range in [">=", "abc", "1.2.3.4", "^1.a", ">= 1 <"] {
+            assert!(range.parse::<VersionReq>().is_err(), "{}", range);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::env::consts::{ARCH, OS};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::thread;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use tar::{Builder, Header};
+use xz2::write::XzEncoder;
+
+const VERSION: &str = "v21.1.0";
+
+/// A throwaway `NVM_DIR` and mirror directory, removed afterwards
+struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("quicknvm-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("nvm")).unwrap();
+        fs::create_dir_all(root.join("mirror")).unwrap();
+        Sandbox { root }
+    }
+
+    fn nvm_dir(&self) -> PathBuf {
+        self.root.join("nvm")
+    }
+
+    fn mirror_dir(&self) -> PathBuf {
+        self.root.join("mirror")
+    }
+
+    /// Publishes a release with a fake `bin/node` in the mirror, with a
+    /// `SHASUMS256.txt` listing `checksum` or the tarball's real one
+    fn publish_release(&self, checksum: Option<&str>) {
+        self.publish_tarball("xz", checksum);
+    }
+
+    fn publish_tarball(&self, extension: &str, checksum: Option<&str>) {
+        let release_dir = self.mirror_dir().join(VERSION);
+        fs::create_dir_all(&release_dir).unwrap();
+
+        let tarball_name = format!("{}.tar.{}", artifact(), extension);
+        let tarball = release_tarball(extension);
+        fs::write(release_dir.join(&tarball_name), &tarball).unwrap();
+
+        let real_checksum = format!("{:x}", Sha256::digest(&tarball));
+        let shasums = format!("{}  {}\n", checksum.unwrap_or(&real_checksum), tarball_name);
+        fs::write(release_dir.join("SHASUMS256.txt"), shasums).unwrap();
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_quicknvm"));
+        command
+            .args(args)
+            .env("NVM_DIR", self.nvm_dir())
+            .env("NVM_NODEJS_ORG_MIRROR", self.mirror_dir())
+            .env_remove("QUICKNVM_NODEJS_MIRROR_FALLBACKS")
+            .env_remove("QUICKNVM_KEYRING");
+        command
+    }
+
+    fn quicknvm(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Serves the mirror over HTTP on localhost under `/dist`, anything
+    /// else is a 404. Returns the server's base URL
+    fn serve_mirror(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mirror_dir = self.mirror_dir();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mirror_dir = mirror_dir.clone();
+                thread::spawn(move || serve_file(stream, &mirror_dir));
+            }
+        });
+
+        format!("http://{}", address)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn artifact() -> String {
+    let os = match OS {
+        "macos" => "darwin",
+        os => os,
+    };
+    let arch = match ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    format!("node-{}-{}-{}", VERSION, os, arch)
+}
+
+fn release_tarball(extension: &str) -> Vec<u8> {
+    let node_script = format!("#!/bin/sh\necho {}\n", VERSION);
+    let mut header = Header::new_gnu();
+    header.set_size(node_script.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+
+    let mut builder = Builder::new(vec![]);
+    builder
+        .append_data(&mut header, format!("{}/bin/node", artifact()), node_script.as_bytes())
+        .unwrap();
+    let tar = builder.into_inner().unwrap();
+
+    match extension {
+        "xz" => {
+            let mut encoder = XzEncoder::new(vec![], 6);
+            encoder.write_all(&tar).unwrap();
+            encoder.finish().unwrap()
+        },
+        _ => {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(&tar).unwrap();
+            encoder.finish().unwrap()
+        },
+    }
+}
+
+fn assert_installed(sandbox: &Sandbox) {
+    let node = sandbox.nvm_dir().join("versions/node").join(VERSION).join("bin/node");
+    let node_output = Command::new(&node).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&node_output.stdout).trim(), VERSION);
+}
+
+fn serve_file(mut stream: TcpStream, mirror_dir: &Path) {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut request_line).unwrap();
+    // Skip the rest of the headers
+    let mut header = String::new();
+    while reader.read_line(&mut header).unwrap() > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split(' ').nth(1).unwrap_or("/");
+    let file = path
+        .strip_prefix("/dist/")
+        .filter(|file| !file.contains(".."))
+        .and_then(|file| fs::read(mirror_dir.join(file)).ok());
+    let (status, body) = match file {
+        Some(body) => ("200 OK", body),
+        None => ("404 Not Found", b"not found".to_vec()),
+    };
+
+    let headers = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+    let _ = stream.write_all(headers.as_bytes());
+    let _ = stream.write_all(&body);
+}
+
+fn is_empty_or_missing(dir: &Path) -> bool {
+    fs::read_dir(dir).map_or(true, |mut entries| entries.next().is_none())
+}
+
+#[test]
+fn installs_from_a_local_mirror() {
+    let sandbox = Sandbox::new("install");
+    sandbox.publish_release(None);
+    // Something nvm downloaded, quicknvm has to leave it alone
+    let nvm_cache = sandbox.nvm_dir().join(".cache/bin").join(artifact());
+    fs::create_dir_all(&nvm_cache).unwrap();
+    fs::write(nvm_cache.join("cached.tar.xz"), "").unwrap();
+
+    let output = sandbox.quicknvm(&["install", &VERSION[1..]]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    assert_installed(&sandbox);
+    assert!(nvm_cache.join("cached.tar.xz").exists());
+    assert!(is_empty_or_missing(&sandbox.nvm_dir().join(".cache/quicknvm")));
+}
+
+#[test]
+fn installs_over_http_from_a_fallback_mirror() {
+    let sandbox = Sandbox::new("http");
+    sandbox.publish_release(None);
+    let server = sandbox.serve_mirror();
+
+    let output = sandbox
+        .command(&["install", &VERSION[1..]])
+        .env("NVM_NODEJS_ORG_MIRROR", format!("{}/missing", server))
+        .env("QUICKNVM_NODEJS_MIRROR_FALLBACKS", format!("{}/dist", server))
+        .env("NO_PROXY", "127.0.0.1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_installed(&sandbox);
+}
+
+#[test]
+fn falls_back_to_gzipped_releases() {
+    let sandbox = Sandbox::new("gzip");
+    sandbox.publish_tarball("gz", None);
+
+    let output = sandbox.quicknvm(&["install", &VERSION[1..]]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_installed(&sandbox);
+}
+
+#[test]
+fn rejects_a_checksum_mismatch() {
+    let sandbox = Sandbox::new("checksum");
+    sandbox.publish_release(Some(&"0".repeat(64)));
+
+    let output = sandbox.quicknvm(&["install", &VERSION[1..]]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("checksum mismatch"));
+
+    // Nothing gets unpacked into nvm's directories
+    assert!(!sandbox.nvm_dir().join("versions/node").join(VERSION).exists());
+    assert!(is_empty_or_missing(&sandbox.nvm_dir().join(".cache/quicknvm")));
+}